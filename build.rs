@@ -0,0 +1,69 @@
+// Generates the tables of the combined automaton of the lexemes at build
+// time, so that lexing starts without compiling any expression.
+//
+// The expressions are read from `src/haskell.lex`, with the same parser
+// as `spec`. The `tables` test checks that they agree with
+// `driver::definitions()`, which is the source of truth.
+
+use regex_automata::dfa::{dense, StartKind};
+use regex_automata::MatchKind;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+#[path = "src/spec_syntax.rs"]
+mod spec_syntax;
+
+const SPECIFICATION : &str = "src/haskell.lex";
+
+/// The patterns of the automaton, in the order of the rules, every lexeme
+/// followed by its `except_for`, if it has one. The hand-written automata
+/// have no pattern.
+fn patterns() -> Vec<String> {
+    let text = fs::read_to_string(SPECIFICATION).expect("the specification is readable");
+    let definitions = spec_syntax::parse(&text)
+        .unwrap_or_else(|error| panic!("{}: {}", SPECIFICATION, error));
+    let mut patterns = vec![];
+    for definition in definitions {
+        if let spec_syntax::Definition::Rule {
+            body : spec_syntax::Body::Regex { expression, except_for }, ..
+        } = definition {
+            patterns.push(expression);
+            patterns.extend(except_for);
+        }
+    }
+    patterns
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/spec_syntax.rs");
+    println!("cargo:rerun-if-changed={}", SPECIFICATION);
+
+    let patterns = patterns();
+    // the same configuration as `CombinedAutomaton::new`
+    let dfa = dense::Builder::new()
+        .configure(dense::Config::new()
+                   .match_kind(MatchKind::All)
+                   .start_kind(StartKind::Anchored))
+        .build_many(&patterns)
+        .expect("the lexeme expressions compile");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    let (bytes, padding) = dfa.to_bytes_little_endian();
+    fs::write(out_dir.join("lexemes.le.dfa"), &bytes[padding..]).unwrap();
+    let (bytes, padding) = dfa.to_bytes_big_endian();
+    fs::write(out_dir.join("lexemes.be.dfa"), &bytes[padding..]).unwrap();
+
+    let mut source = String::from("/// The patterns the tables are made of.\n");
+    source += "pub const PATTERNS : &[&str] = &[\n";
+    for pattern in &patterns {
+        source += &format!("    {:?},\n", pattern);
+    }
+    source += "];\n";
+    fs::write(out_dir.join("patterns.rs"), source).unwrap();
+}
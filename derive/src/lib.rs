@@ -0,0 +1,319 @@
+//! `#[derive(RegexLexeme)]`, which writes the `impl regex::RegexLexeme`
+//! of a lexeme out of its attribute
+//!
+//! ```text
+//! #[derive(RegexLexeme)]
+//! #[lexeme(regex = "[A-Z][a-z]*", token = QConId, except = "Main")]
+//! pub struct ConId {}
+//!
+//! #[derive(RegexLexeme)]
+//! #[lexeme(regex = [qualifier(), "[", reused::Large, "]*"], token = QConId,
+//!          except = reserved::ReservedId)]
+//! pub struct QualifiedConId {}
+//! ```
+//!
+//! - `regex` is the expression of the lexeme, or `group` instead, which
+//!   is the inside of a character group, like `"0-9"`;
+//! - `token` is a variant of `token::TokenType`;
+//! - `except`, optional, is what the matches must not be as a whole.
+//!
+//! An expression is either a string, or made of pieces pasted one after
+//! another, `[...]`, the same way the lexemes built with `format!` do: a
+//! string is pasted as it is, a path names another lexeme, whose
+//! expression is pasted, and anything else is an expression of a `&str`
+//! or a `String`, like a call. A lexeme alone is written without `[]`.
+//!
+//! The strings are compiled when the macro runs, into the same dense DFA
+//! as `CombinedAutomaton::new` makes, so that an invalid one is a compile
+//! error, and not a surprise at runtime. The macro cannot see the pieces
+//! of other lexemes, so it writes a test next to the lexeme as well, which
+//! compiles the whole expression with `RegexLexeme::check()`; the lexeme
+//! has to be defined in a module, not in a function, for the test to see
+//! it.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::spanned::Spanned;
+use syn::{DeriveInput, Expr, Ident, Lit};
+
+use regex_automata::dfa::{dense, StartKind};
+use regex_automata::MatchKind;
+
+#[proc_macro_derive(RegexLexeme, attributes(lexeme))]
+pub fn derive_regex_lexeme(input : proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// An expression of the attribute.
+#[derive(Debug, PartialEq)]
+enum Expression {
+    /// Strings only, checked when the macro runs.
+    Literal(String),
+    /// With pieces of other lexemes, put together when first used.
+    Composed(Vec<Piece>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Literal(String),
+    /// Another lexeme, its expression is pasted.
+    Lexeme(syn::Path),
+    /// A `&str` or a `String`.
+    Text(Expr),
+}
+
+/// What the `#[lexeme(...)]` attribute says.
+#[derive(Debug, PartialEq)]
+struct Attributes {
+    expression : Expression,
+    token : Ident,
+    except : Option<Expression>,
+}
+
+fn expand(input : &DeriveInput) -> syn::Result<TokenStream> {
+    let attributes = parse_attributes(input)?;
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let expression = string_of(&attributes.expression);
+    let token = &attributes.token;
+
+    let filtering = attributes.except.as_ref().map(|except| {
+        let except = string_of(except);
+        quote! {
+            fn needs_filtering() -> bool { true }
+
+            fn except_for() -> &'static str { #except }
+        }
+    });
+
+    // a generic lexeme has no one type to check
+    let test = if input.generics.params.is_empty() {
+        let module = format_ident!("__{}_expressions", name);
+        Some(quote! {
+            #[cfg(test)]
+            #[allow(non_snake_case)]
+            mod #module {
+                #[test]
+                fn are_valid() {
+                    use ::proof_of_concept_haskell_lexer::regex::RegexLexeme;
+                    if let Err(error) = <super::#name as RegexLexeme>::check() {
+                        panic!("{}", error)
+                    }
+                }
+            }
+        })
+    } else {
+        None
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::proof_of_concept_haskell_lexer::regex::RegexLexeme
+            for #name #type_generics #where_clause
+        {
+            fn expression() -> &'static str { #expression }
+
+            fn token_type() -> ::proof_of_concept_haskell_lexer::token::TokenType {
+                ::proof_of_concept_haskell_lexer::token::TokenType::#token
+            }
+
+            #filtering
+        }
+
+        #test
+    })
+}
+
+/// The body of a function that returns `expression` as a `&'static str`,
+/// a composed one is put together once.
+fn string_of(expression : &Expression) -> TokenStream {
+    let pieces = match expression {
+        Expression::Literal(literal) => return literal.to_token_stream(),
+        Expression::Composed(pieces) => pieces,
+    };
+    let pieces : Vec<_> = pieces.iter().map(|piece| match piece {
+        Piece::Literal(literal) => literal.to_token_stream(),
+        Piece::Lexeme(path) => quote! {
+            <#path as ::proof_of_concept_haskell_lexer::regex::RegexLexeme>::expression()
+        },
+        Piece::Text(text) => quote! { &#text },
+    }).collect();
+    quote! {
+        static EXPRESSION : ::proof_of_concept_haskell_lexer::regex::OnceCell<
+                ::std::string::String>
+            = ::proof_of_concept_haskell_lexer::regex::OnceCell::new();
+        EXPRESSION.get_or_init(|| {
+            let mut expression = ::std::string::String::new();
+            #( expression.push_str(#pieces); )*
+            expression
+        })
+    }
+}
+
+fn parse_attributes(input : &DeriveInput) -> syn::Result<Attributes> {
+    let mut expression : Option<Expression> = None;
+    let mut token : Option<Ident> = None;
+    let mut except : Option<Expression> = None;
+
+    for attribute in input.attrs.iter().filter(|attribute| attribute.path().is_ident("lexeme")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("regex") || meta.path.is_ident("group") {
+                if expression.is_some() {
+                    return Err(meta.error("the expression is given twice"))
+                }
+                let group = meta.path.is_ident("group");
+                expression = Some(parse_expression(&meta.value()?.parse()?, group)?);
+                Ok(())
+            } else if meta.path.is_ident("token") {
+                token = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("except") {
+                except = Some(parse_expression(&meta.value()?.parse()?, false)?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `regex`, `group`, `token` or `except`"))
+            }
+        })?;
+    }
+
+    let missing = |what : &str| syn::Error::new(
+        Span::call_site(), format!("#[lexeme(...)] needs {}", what));
+    Ok(Attributes {
+        expression : expression.ok_or_else(|| missing("`regex` or `group`"))?,
+        token : token.ok_or_else(|| missing("`token`"))?,
+        except,
+    })
+}
+
+/// Reads the value of `regex`, `group` or `except`, and checks it when it
+/// is made of strings only; a `group` is checked inside of `[]`.
+fn parse_expression(value : &Expr, group : bool) -> syn::Result<Expression> {
+    let pieces = match value {
+        Expr::Array(array) => array.elems.iter().map(parse_piece).collect::<syn::Result<_>>()?,
+        Expr::Lit(_) | Expr::Path(_) => vec![parse_piece(value)?],
+        _ => return Err(syn::Error::new(value.span(),
+                                        "expected a string, a lexeme, or a list of pieces")),
+    };
+
+    let mut literal = String::new();
+    for piece in &pieces {
+        match piece {
+            Piece::Literal(piece) => literal += piece,
+            _ => return Ok(Expression::Composed(pieces)),
+        }
+    }
+    if group {
+        validate(value, &format!("[{}]", literal))?;
+    } else {
+        validate(value, &literal)?;
+    }
+    Ok(Expression::Literal(literal))
+}
+
+fn parse_piece(piece : &Expr) -> syn::Result<Piece> {
+    Ok(match piece {
+        Expr::Lit(literal) => match &literal.lit {
+            Lit::Str(literal) => Piece::Literal(literal.value()),
+            _ => return Err(syn::Error::new(literal.span(), "expected a string")),
+        },
+        Expr::Path(path) => Piece::Lexeme(path.path.clone()),
+        _ => Piece::Text(piece.clone()),
+    })
+}
+
+/// Compiles `expression` the way `CombinedAutomaton::new` does, the error
+/// points at `value`.
+fn validate(value : &Expr, expression : &str) -> syn::Result<()> {
+    let built = dense::Builder::new()
+        .configure(dense::Config::new()
+                   .match_kind(MatchKind::All)
+                   .start_kind(StartKind::Anchored))
+        .build(expression);
+    match built {
+        Ok(_) => Ok(()),
+        Err(error) => Err(syn::Error::new(value.span(),
+                                          format!("invalid expression: {}", error))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(source : &str) -> syn::Result<Attributes> {
+        parse_attributes(&syn::parse_str(source).unwrap())
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(parse(r#"#[lexeme(regex = "a+", token = QVarId, except = "aa")] struct A;"#)
+                   .unwrap(),
+                   Attributes {
+                       expression : Expression::Literal("a+".to_string()),
+                       token : Ident::new("QVarId", Span::call_site()),
+                       except : Some(Expression::Literal("aa".to_string())),
+                   });
+        assert_eq!(parse(r#"#[lexeme(group = "0-7", token = Octit)] struct A;"#)
+                   .unwrap().expression, Expression::Literal("0-7".to_string()));
+        assert_eq!(parse(r#"#[lexeme(regex = ["[0-9]", "+"], token = Octit)] struct A;"#)
+                   .unwrap().expression, Expression::Literal("[0-9]+".to_string()));
+    }
+
+    #[test]
+    fn composed() {
+        let attributes = parse(r#"
+            #[lexeme(regex = [qualifier(), "[", reused::Large, "]*"], token = QConId,
+                     except = reserved::ReservedId)]
+            struct A;"#).unwrap();
+        assert_eq!(attributes.expression, Expression::Composed(vec![
+            Piece::Text(syn::parse_str("qualifier()").unwrap()),
+            Piece::Literal("[".to_string()),
+            Piece::Lexeme(syn::parse_str("reused::Large").unwrap()),
+            Piece::Literal("]*".to_string()),
+        ]));
+        assert_eq!(attributes.except, Some(Expression::Composed(vec![
+            Piece::Lexeme(syn::parse_str("reserved::ReservedId").unwrap()),
+        ])));
+    }
+
+    #[test]
+    fn generated() {
+        let expanded = |source : &str| expand(&syn::parse_str(source).unwrap()).unwrap().to_string();
+        let composed = expanded(r#"#[lexeme(regex = [Digit, "+"], token = QVarId)] struct A;"#);
+        assert!(composed.contains("OnceCell"));
+        assert!(composed.contains("mod __A_expressions"));
+        let generic = expanded(r#"#[lexeme(regex = "a", token = QVarId)] struct A<T>(T);"#);
+        assert!(!generic.contains("mod"));
+    }
+
+    #[test]
+    fn invalid() {
+        let message = |source : &str| parse(source).unwrap_err().to_string();
+        assert!(message(r#"#[lexeme(regex = "(a", token = QVarId)] struct A;"#)
+                .starts_with("invalid expression"));
+        assert!(message(r#"#[lexeme(group = "\\", token = QVarId)] struct A;"#)
+                .starts_with("invalid expression"));
+        assert!(message(r#"#[lexeme(regex = "a", token = QVarId, except = "[")] struct A;"#)
+                .starts_with("invalid expression"));
+        // fine for the regex crate, but not for a DFA
+        assert!(message(r#"#[lexeme(regex = "a\\b", token = QVarId)] struct A;"#)
+                .starts_with("invalid expression"));
+        assert!(message(r#"#[lexeme(regex = ["(", "a"], token = QVarId)] struct A;"#)
+                .starts_with("invalid expression"));
+        assert_eq!(message(r#"#[lexeme(regex = 1, token = QVarId)] struct A;"#),
+                   "expected a string");
+        assert_eq!(message(r#"#[lexeme(regex = a + b, token = QVarId)] struct A;"#),
+                   "expected a string, a lexeme, or a list of pieces");
+        assert_eq!(message(r#"#[lexeme(regex = "a")] struct A;"#),
+                   "#[lexeme(...)] needs `token`");
+        assert_eq!(message(r#"#[lexeme(token = QVarId)] struct A;"#),
+                   "#[lexeme(...)] needs `regex` or `group`");
+        assert_eq!(message(r#"#[lexeme(regex = "a", group = "a", token = QVarId)] struct A;"#),
+                   "the expression is given twice");
+        assert!(message(r#"#[lexeme(regexp = "a", token = QVarId)] struct A;"#)
+                .starts_with("expected"));
+    }
+}
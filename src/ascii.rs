@@ -1,17 +0,0 @@
-pub struct ASCIIChar {
-    character : char,
-}
-
-impl ASCIIChar {
-    pub fn new(character : char) -> Option<Self> {
-        if character.is_ascii() {
-            Option::Some(Self {character : character} )
-        } else {
-            Option::None
-        }
-    }
-
-    pub fn get_char(&self) -> char {
-        self.character
-    }
-}
@@ -0,0 +1,496 @@
+//! One automaton for all the lexemes, so that the input is read once per
+//! token, instead of once per lexeme type.
+//!
+//! The expressions of the regex lexemes are compiled together into a
+//! single DFA, where every pattern is tagged with the token type and the
+//! priority of its lexeme. The `except_for` expressions are patterns of
+//! the DFA too, a match is left out when the exception matches at the same
+//! place. The hand-written automata, like the one of `mlcomment`, read the
+//! same characters in lockstep. One forward pass finds the longest match
+//! of every lexeme, and the longest of all of them wins, the one defined
+//! first on equal lengths.
+//!
+//! The automaton is either built at runtime with `CombinedAutomaton::new`,
+//! or read from the tables that `build.rs` generates, see `tables`.
+
+use crate::dfa;
+use crate::lexeme;
+use crate::limits;
+use crate::token;
+
+use regex_automata::dfa::{dense, Automaton};
+use regex_automata::util::primitives::StateID;
+use regex_automata::{Anchored, Input, MatchKind};
+
+/// How a lexeme is recognized.
+pub enum Definition {
+    /// By a regular expression, with the matches that are covered by
+    /// `except_for` as a whole left out, like a `RegexLexeme`.
+    Regex {
+        token_type : token::TokenType,
+        expression : String,
+        except_for : Option<String>,
+    },
+    /// By a hand-written automaton, the lexeme ends as soon as the
+    /// automaton reaches a final state.
+    Automaton {
+        token_type : token::TokenType,
+        start : fn() -> Box<dyn dfa::CharDFA>,
+    },
+}
+
+impl Definition {
+    /// The definition of a `RegexLexeme`.
+    pub fn of<T : crate::regex::RegexLexeme>() -> Self {
+        Definition::Regex {
+            token_type : T::token_type(),
+            expression : T::expression().to_string(),
+            except_for : if T::needs_filtering() {
+                Some(T::except_for().to_string())
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn token_type(&self) -> token::TokenType {
+        match self {
+            Definition::Regex { token_type, .. } => *token_type,
+            Definition::Automaton { token_type, .. } => *token_type,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// An expression does not compile into the automaton.
+    Build(Box<dense::BuildError>),
+    /// The tables are not a DFA.
+    Deserialize(regex_automata::util::wire::DeserializeError),
+    /// The tables are a DFA, but not one of these definitions.
+    TablesMismatch,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Build(error) => write!(f, "cannot build the automaton: {}", error),
+            Error::Deserialize(error) => write!(f, "cannot read the tables: {}", error),
+            Error::TablesMismatch =>
+                write!(f, "the tables do not match the definitions"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Build(error) => Some(error),
+            Error::Deserialize(error) => Some(error),
+            Error::TablesMismatch => None,
+        }
+    }
+}
+
+/// The longest lexeme at the beginning of the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Match {
+    pub token_type : token::TokenType,
+    /// The index of the definition, lower wins on equal lengths.
+    pub priority : usize,
+    /// Length in bytes.
+    pub len : usize,
+}
+
+/// What a pattern of the DFA stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Role {
+    /// The expression of the definition with this index.
+    Lexeme(usize),
+    /// Its `except_for`.
+    Except(usize),
+}
+
+/// The patterns of the DFA of `definitions`, in order: the expression of
+/// every regex definition, followed by its `except_for`, if it has one.
+pub fn patterns(definitions : &[Definition]) -> Vec<&str> {
+    roles(definitions).into_iter().map(|(pattern, _)| pattern).collect()
+}
+
+fn roles(definitions : &[Definition]) -> Vec<(&str, Role)> {
+    let mut roles = vec![];
+    for (index, definition) in definitions.iter().enumerate() {
+        if let Definition::Regex { expression, except_for, .. } = definition {
+            roles.push((expression.as_str(), Role::Lexeme(index)));
+            if let Some(except_for) = except_for {
+                roles.push((except_for.as_str(), Role::Except(index)));
+            }
+        }
+    }
+    roles
+}
+
+/// What `longest_match` needs to remember while it reads a token. It is
+/// made once, and reused for every token, so that lexing does not
+/// allocate.
+pub struct Scratch {
+    // the longest match of every definition so far, and whether the
+    // exception of the definition matches it too
+    longest : Vec<Option<(usize, bool)>>,
+    // the hand-written automata, by definition, and whether they still run
+    automata : Vec<(usize, Box<dyn dfa::CharDFA>, bool)>,
+    limits : limits::LexerLimits,
+    // how many characters were read, so that tests can tell that lexing
+    // reads the input a bounded number of times
+    #[cfg(test)]
+    pub(crate) read : usize,
+}
+
+impl Scratch {
+    /// Makes `longest_match_with` keep to `limits`, the length of tokens
+    /// and the ones of the hand-written automata.
+    pub fn set_limits(&mut self, limits : limits::LexerLimits) {
+        self.limits = limits;
+    }
+}
+
+pub struct CombinedAutomaton<T = Vec<u32>> {
+    dfa : dense::DFA<T>,
+    definitions : Vec<Definition>,
+    // the role of every pattern of the DFA
+    roles : Vec<Role>,
+}
+
+impl CombinedAutomaton<Vec<u32>> {
+    /// Builds the automaton, the definitions are listed by priority.
+    pub fn new(definitions : Vec<Definition>) -> Result<Self, Error> {
+        let roles = roles(&definitions);
+        let patterns : Vec<&str> = roles.iter().map(|(pattern, _)| *pattern).collect();
+
+        // every pattern reports all of its matches, so that the longest
+        // one of each can be found, not only the longest one overall;
+        // `build.rs` uses the same configuration
+        let dfa = dense::Builder::new()
+            .configure(dense::Config::new()
+                       .match_kind(MatchKind::All)
+                       .start_kind(regex_automata::dfa::StartKind::Anchored))
+            .build_many(&patterns)
+            .map_err(|error| Error::Build(Box::new(error)))?;
+
+        let roles = roles.into_iter().map(|(_, role)| role).collect();
+        Ok(Self { dfa, definitions, roles })
+    }
+}
+
+impl CombinedAutomaton<&'static [u32]> {
+    /// Reads the automaton of `definitions` from tables, as made by
+    /// `to_bytes()`. Nothing is compiled, and the tables are not copied.
+    pub fn from_tables(definitions : Vec<Definition>, tables : &'static [u8])
+                       -> Result<Self, Error> {
+        let (dfa, _) = dense::DFA::from_bytes(tables).map_err(Error::Deserialize)?;
+        let roles : Vec<Role> = roles(&definitions).into_iter()
+            .map(|(_, role)| role).collect();
+        if dfa.pattern_len() != roles.len() {
+            return Err(Error::TablesMismatch)
+        }
+        Ok(Self { dfa, definitions, roles })
+    }
+}
+
+impl<T : AsRef<[u32]>> CombinedAutomaton<T> {
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// The tables of the DFA, in the byte order of the target.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (bytes, padding) = self.dfa.to_bytes_native_endian();
+        bytes[padding..].to_vec()
+    }
+
+    /// A scratch space for `longest_match_with`.
+    pub fn scratch(&self) -> Scratch {
+        Scratch {
+            longest : vec![None; self.definitions.len()],
+            automata : self.definitions.iter()
+                .enumerate()
+                .filter_map(|(index, definition)| match definition {
+                    Definition::Automaton { start, .. } => Some((index, start(), true)),
+                    Definition::Regex { .. } => None,
+                })
+                .collect(),
+            limits : limits::LexerLimits::default(),
+            #[cfg(test)]
+            read : 0,
+        }
+    }
+
+    /// Finds the longest lexeme at the beginning of `input`. When there
+    /// is none, tells why, if a lexeme started, but went wrong, as a
+    /// hand-written automaton found.
+    pub fn longest_match(&self, input : &str) -> Result<Match, lexeme::Error> {
+        self.longest_match_with(input, &mut self.scratch())
+    }
+
+    /// Same as `longest_match`, with a scratch space that was made by
+    /// `scratch()` of this automaton.
+    pub fn longest_match_with(&self, input : &str, scratch : &mut Scratch)
+                              -> Result<Match, lexeme::Error> {
+        let longest = &mut scratch.longest;
+        longest.iter_mut().for_each(|longest| *longest = None);
+        let automata = &mut scratch.automata;
+        for (_, automaton, running) in automata.iter_mut() {
+            automaton.reset();
+            automaton.set_limits(&scratch.limits);
+            *running = true;
+        }
+        let max_len = scratch.limits.max_token_length;
+        let mut running = automata.len();
+
+        let mut state = Some(self.dfa.start_state_forward(
+            &Input::new(input).anchored(Anchored::Yes))
+                             .expect("the automaton has an anchored start"));
+
+        for (offset, character) in input.char_indices() {
+            if state.is_none() && running == 0 {
+                break
+            }
+            #[cfg(test)]
+            {
+                scratch.read += 1;
+            }
+
+            if let Some(current) = state {
+                let mut encoded = [0; 4];
+                let mut next = current;
+                for (index, byte) in character.encode_utf8(&mut encoded).bytes().enumerate() {
+                    next = self.dfa.next_state(next, byte);
+                    // the DFA is one byte late to report a match
+                    self.record(next, offset + index, longest);
+                    if self.dfa.is_dead_state(next) {
+                        break
+                    }
+                }
+                state = if self.dfa.is_dead_state(next) { None } else { Some(next) };
+            }
+
+            let end = offset + character.len_utf8();
+            for (index, automaton, still) in automata.iter_mut().filter(|(_, _, still)| *still) {
+                automaton.advance_char(character);
+                if automaton.is_final() {
+                    longest[*index] = Some((end, false));
+                    *still = false;
+                } else if automaton.is_failed() {
+                    *still = false;
+                }
+                if !*still {
+                    running -= 1;
+                }
+            }
+
+            // read no further than a character past the limit, the DFA
+            // only finds out then whether a match ends at the limit
+            if offset > max_len && (state.is_some() || running > 0
+                                    || longest.iter().flatten().any(|(len, _)| *len > max_len)) {
+                return Err(lexeme::Error::TokenTooLong { limit : max_len })
+            }
+        }
+
+        if let Some(current) = state {
+            self.record(self.dfa.next_eoi_state(current), input.len(), longest);
+        }
+
+        let mut best : Option<Match> = None;
+        for (priority, found) in longest.iter().enumerate() {
+            match found {
+                Some((len, false)) if *len > best.map_or(0, |best| best.len) => {
+                    best = Some(Match {
+                        token_type : self.definitions[priority].token_type(),
+                        priority,
+                        len : *len,
+                    });
+                },
+                _ => {},
+            }
+        }
+
+        match best {
+            Some(best) if best.len > max_len =>
+                Err(lexeme::Error::TokenTooLong { limit : max_len }),
+            Some(best) => Ok(best),
+            // an automaton that stopped early keeps the error it failed with
+            None => Err(automata.iter()
+                        .find_map(|(_, automaton, _)| automaton.error())
+                        .unwrap_or(lexeme::Error::NotRecognized)),
+        }
+    }
+
+    /// Notes the matches that end at `end`, if the DFA is in a match
+    /// state. The exception of a lexeme only counts when it matches at
+    /// the same end as the lexeme.
+    fn record(&self, state : StateID, end : usize, longest : &mut [Option<(usize, bool)>]) {
+        if !self.dfa.is_match_state(state) {
+            return
+        }
+        let roles = || (0..self.dfa.match_len(state))
+            .map(|index| self.roles[self.dfa.match_pattern(state, index).as_usize()]);
+        for role in roles() {
+            if let Role::Lexeme(definition) = role {
+                longest[definition] = Some((end, false));
+            }
+        }
+        for role in roles() {
+            if let Role::Except(definition) = role {
+                if let Some((len, excluded)) = &mut longest[definition] {
+                    *excluded |= *len == end;
+                }
+            }
+        }
+    }
+}
+
+/// What the driver needs of a combined automaton, whether its tables are
+/// generated at build time or built at runtime.
+pub trait Recognizer : Sync {
+    fn scratch(&self) -> Scratch;
+
+    fn longest_match_with(&self, input : &str, scratch : &mut Scratch)
+                          -> Result<Match, lexeme::Error>;
+}
+
+impl<T : AsRef<[u32]> + Sync> Recognizer for CombinedAutomaton<T> {
+    fn scratch(&self) -> Scratch {
+        CombinedAutomaton::scratch(self)
+    }
+
+    fn longest_match_with(&self, input : &str, scratch : &mut Scratch)
+                          -> Result<Match, lexeme::Error> {
+        CombinedAutomaton::longest_match_with(self, input, scratch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexeme::Lexeme;
+    use crate::*;
+
+    type Recognizer = fn(&str) -> Result<token::Token<'_>, lexeme::Error>;
+
+    fn automaton() -> CombinedAutomaton {
+        CombinedAutomaton::new(driver::definitions()).unwrap()
+    }
+
+    /// What trying the recognizers of `driver::definitions()` one by one
+    /// gives.
+    fn one_by_one(input : &str) -> Option<(token::TokenType, usize)> {
+        let recognizers : [Recognizer; 13] = [
+            mlcomment::MLComment::recognize,
+            reserved::ReservedId::recognize,
+            reserved::ReservedOp::recognize,
+            qident::QConId::recognize,
+            qident::QVarId::recognize,
+            qident::QVarSym::recognize,
+            qident::QConSym::recognize,
+            literals::Integer::recognize,
+            literals::Float::recognize,
+            literals::Char::recognize,
+            string::StringLiteral::recognize,
+            comment::SLComment::recognize,
+            pragma::Pragma::recognize,
+        ];
+        let mut longest : Option<(token::TokenType, usize)> = None;
+        for recognizer in &recognizers {
+            if let Ok(token) = recognizer(input) {
+                if token.span.len() > longest.map_or(0, |(_, len)| len) {
+                    longest = Some((token.token_type, token.span.len()));
+                }
+            }
+        }
+        longest
+    }
+
+    #[test]
+    fn longest_match() {
+        use token::TokenType::*;
+        let automaton = automaton();
+        let found = |input : &str| automaton.longest_match(input)
+            .map(|found| (found.token_type, found.len));
+        assert_eq!(found("where x"), Ok((ReservedId, 5)));
+        assert_eq!(found("wherever"), Ok((QVarId, 8)));
+        assert_eq!(found("infixl 6"), Ok((ReservedId, 6)));
+        assert_eq!(found("-> x"), Ok((ReservedOp, 2)));
+        assert_eq!(found("--> x"), Ok((QVarSym, 3)));
+        assert_eq!(found("M.x.y"), Ok((QVarId, 3)));
+        assert_eq!(found("{- {- -} -} x"), Ok((MLComment, 11)));
+        assert_eq!(found("\u{3bb}x"), Ok((QVarId, 3)));
+        assert_eq!(found("{- {- x -}"), Err(lexeme::Error::UnterminatedBlockComment {
+            outermost : span::Position::default(),
+            nested : vec![],
+            depth : 1,
+        }));
+        assert_eq!(found("{-# INLINE f #-} x"), Ok((Pragma, 16)));
+        assert_eq!(found("{-# INLINE f"), Err(lexeme::Error::UnterminatedPragma));
+        assert_eq!(found("{"), Err(lexeme::Error::NotRecognized));
+        assert_eq!(found("\"x\" y"), Ok((StringLiteral, 3)));
+        assert_eq!(found("\"x\ny\""),
+                   Err(lexeme::Error::String(string::Error::EndOfLine)));
+        assert_eq!(found("\"x"), Err(lexeme::Error::String(string::Error::EndOfInput)));
+    }
+
+    #[test]
+    fn token_length() {
+        use token::TokenType::*;
+        let automaton = automaton();
+        let mut scratch = automaton.scratch();
+        scratch.set_limits(limits::LexerLimits { max_token_length : 4,
+                                                 ..limits::LexerLimits::default() });
+        let mut found = |input : &str| automaton.longest_match_with(input, &mut scratch)
+            .map(|found| (found.token_type, found.len));
+        assert_eq!(found("abcd e"), Ok((QVarId, 4)));
+        assert_eq!(found("{--}"), Ok((MLComment, 4)));
+        assert_eq!(found("abcde"), Err(lexeme::Error::TokenTooLong { limit : 4 }));
+        assert_eq!(found("12.5e x"), Ok((FloatLiteral, 4)));
+        // it may go on, so it is too long, even though it does not
+        assert_eq!(found("12.5e+ x"), Err(lexeme::Error::TokenTooLong { limit : 4 }));
+        assert_eq!(found("{- x -}"), Err(lexeme::Error::TokenTooLong { limit : 4 }));
+    }
+
+    #[test]
+    fn priority() {
+        let automaton = automaton();
+        let found = automaton.longest_match("case").unwrap();
+        assert_eq!(found.priority, 1);
+        assert_eq!(found.token_type, automaton.definitions()[1].token_type());
+    }
+
+    #[test]
+    fn invalid_expression() {
+        let result = CombinedAutomaton::new(vec![Definition::Regex {
+            token_type : token::TokenType::Error,
+            expression : "(unclosed".to_string(),
+            except_for : None,
+        }]);
+        assert!(matches!(result, Err(Error::Build(_))));
+    }
+
+    #[test]
+    fn agrees_with_the_recognizers() {
+        let pieces = ["x", "X", "M.", ".", "'", "_", "in", "infix", "l", "where",
+                      "-", ">", ":", "=", "\\", "|", "+", "{-", "-}", "{", "}",
+                      " ", "\u{3bb}", "\u{3a3}", "\u{2192}", "\u{663}", "0", "1",
+                      "x", "o", "F", "8", "e", "E-", "+", "'", "\\^", "NUL", "SO", "H", "&",
+                      "\"", "\n", "\t", "--", "---", "|"];
+        let automaton = automaton();
+        let mut random = test_util::Random(0x0123_4567_89ab_cdef);
+        for _ in 0..2000 {
+            let input = random.pieces(&pieces, 8);
+            if input.is_empty() {
+                continue
+            }
+            let found = automaton.longest_match(&input).ok()
+                .map(|found| (found.token_type, found.len));
+            assert_eq!(found, one_by_one(&input), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Line comments.
+//!
+//! comment -> dashes [ any<symbol> { any } ] newline
+//! dashes -> -- { - }
+//!
+//! Two or more dashes start a comment only when no other symbol follows
+//! them, `-->` and `|--` are operators, while `--`, `---x` and `-- |` are
+//! comments. The newline is not a part of the comment, it is whitespace.
+//!
+//! Haddock documentation comments are comments too, the driver tells them
+//! apart with `doc_kind`, and `doc_blocks` groups the line comments that
+//! make one piece of documentation.
+
+use crate::reused;
+use crate::span;
+use crate::token;
+
+use crate::RegexLexeme;
+
+/// dashes -> -- { - }
+pub(crate) fn dashes() -> &'static str {
+    "-{2,}"
+}
+
+#[derive(RegexLexeme)]
+#[lexeme(regex = [dashes(), r"([^\n\r\x0C", reused::Symbol, r"][^\n\r\x0C]*)?"],
+         token = SLComment)]
+pub struct SLComment {}
+
+/// What a Haddock comment documents.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum DocKind {
+    /// `-- |` or `{-|`, documents what follows.
+    Next,
+    /// `-- ^` or `{-^`, documents what precedes.
+    Previous,
+    /// `-- *`, a section heading, the level is the number of stars.
+    Section(usize),
+    /// `-- $name`, a named chunk, which is placed elsewhere by its name.
+    Named(String),
+}
+
+/// Tells if a comment of `token_type` is a Haddock comment, and which
+/// kind, by the marker after its dashes or its `{-`. The whitespace before
+/// the marker does not matter.
+pub fn doc_kind(token_type : token::TokenType, text : &str) -> Option<DocKind> {
+    let (body, block) = match token_type {
+        token::TokenType::SLComment => (text.trim_start_matches('-'), false),
+        token::TokenType::MLComment => (text.strip_prefix("{-")?, true),
+        _ => return None,
+    };
+    let body = body.trim_start_matches(reused::is_whitechar);
+    match body.chars().next()? {
+        '|' => Some(DocKind::Next),
+        '^' => Some(DocKind::Previous),
+        '*' if !block => Some(DocKind::Section(body.len() - body.trim_start_matches('*').len())),
+        '$' if !block => {
+            let name = &body[1..];
+            let len = name.find(|character : char| {
+                !(character.is_alphanumeric() || character == '_' || character == '\'')
+            }).unwrap_or(name.len());
+            if len > 0 {
+                Some(DocKind::Named(name[..len].to_string()))
+            } else {
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+/// One piece of documentation: a Haddock comment, and the plain line
+/// comments that continue it on the lines right after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocBlock<'src> {
+    pub kind : DocKind,
+    /// From the beginning of the first comment to the end of the last one.
+    pub span : span::Span,
+    /// The first one has the marker.
+    pub comments : Vec<token::Token<'src>>,
+}
+
+/// Finds the Haddock comments in `tokens`, which the driver made, in
+/// order. A line comment continues the block of the comment right before
+/// it, when it is a plain one, on the next line; block comments and
+/// section headings stand alone.
+pub fn doc_blocks<'src>(tokens : &[token::Token<'src>]) -> Vec<DocBlock<'src>> {
+    let mut blocks : Vec<DocBlock<'src>> = vec![];
+    // whether the last block may still take the next line comment
+    let mut open = false;
+    for token in tokens.iter().filter(|token| !matches!(
+        token.token_type, token::TokenType::Whitespace | token::TokenType::Newline)) {
+        match token.doc() {
+            Some(kind) => {
+                open = token.token_type == token::TokenType::SLComment
+                    && !matches!(kind, DocKind::Section(_));
+                blocks.push(DocBlock { kind : kind.clone(),
+                                       span : token.span,
+                                       comments : vec![token.clone()] });
+            },
+            None => {
+                let last = blocks.last_mut().filter(|_| open);
+                match last {
+                    Some(last) if token.token_type == token::TokenType::SLComment
+                        && token.span.start.line == last.span.end.line + 1 => {
+                        last.span = last.span.merge(token.span);
+                        last.comments.push(token.clone());
+                    },
+                    _ => open = false,
+                }
+            },
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver;
+    use crate::lexeme::Lexeme;
+
+    fn comment(input : &str) -> Option<&str> {
+        SLComment::recognize(input).ok().map(|token| token.text)
+    }
+
+    #[test]
+    fn dashes_rule() {
+        assert_eq!(comment("-- a comment\nx"), Some("-- a comment"));
+        assert_eq!(comment("--"), Some("--"));
+        assert_eq!(comment("---x"), Some("---x"));
+        assert_eq!(comment("-- | doc\r\n"), Some("-- | doc"));
+        assert_eq!(comment("--\u{3bb} -> x"), Some("--\u{3bb} -> x"));
+        // a symbol after the dashes makes them an operator
+        assert_eq!(comment("-->"), Some("--"));
+        assert_eq!(comment("--\u{2192}"), Some("--"));
+        assert_eq!(comment("-x"), None);
+        assert_eq!(comment("|--"), None);
+    }
+
+    #[test]
+    fn doc_kinds() {
+        use token::TokenType::{MLComment, QVarId, QVarSym, SLComment};
+        assert_eq!(doc_kind(SLComment, "-- | The answer"), Some(DocKind::Next));
+        // as the lexer cuts them out of the source, where `--|` is an operator
+        let kinds : Vec<_> = driver::Lexer::new("x -- |\ny --  ^\nx--|y")
+            .map(|token| token.unwrap())
+            .map(|token| (token.token_type, doc_kind(token.token_type, token.text)))
+            .collect();
+        assert_eq!(kinds, vec![
+            (QVarId, None), (SLComment, Some(DocKind::Next)),
+            (QVarId, None), (SLComment, Some(DocKind::Previous)),
+            (QVarId, None), (QVarSym, None), (QVarId, None),
+        ]);
+        assert_eq!(doc_kind(SLComment, "-- ^ the field"), Some(DocKind::Previous));
+        assert_eq!(doc_kind(SLComment, "-- * Section"), Some(DocKind::Section(1)));
+        assert_eq!(doc_kind(SLComment, "-- ** Subsection"), Some(DocKind::Section(2)));
+        assert_eq!(doc_kind(SLComment, "-- $chunk'1 text"),
+                   Some(DocKind::Named("chunk'1".to_string())));
+        assert_eq!(doc_kind(MLComment, "{-| The answer -}"), Some(DocKind::Next));
+        assert_eq!(doc_kind(MLComment, "{-^ the field -}"), Some(DocKind::Previous));
+        for plain in &["-- a comment", "--", "-- $", "---x", "-- x | y"] {
+            assert_eq!(doc_kind(SLComment, plain), None, "{}", plain);
+        }
+        assert_eq!(doc_kind(MLComment, "{- plain -}"), None);
+        assert_eq!(doc_kind(MLComment, "{-* not a heading -}"), None);
+        assert_eq!(doc_kind(QVarSym, "--|"), None);
+    }
+
+    #[test]
+    fn grouping() {
+        let source = "\
+-- | The answer,
+-- to everything.
+answer = 42 -- ^ forty-two
+  -- and more about it
+
+-- * Section
+-- plain
+-- | Separated
+--   by nothing.
+
+--   after an empty line
+{-| A block -}
+-- plain
+";
+        let tokens : Vec<_> = driver::Lexer::new(source).map(|token| token.unwrap()).collect();
+        let blocks = doc_blocks(&tokens);
+        let summary : Vec<_> = blocks.iter()
+            .map(|block| (block.kind.clone(), block.span.start.line, block.span.end.line,
+                          block.comments.len()))
+            .collect();
+        assert_eq!(summary, vec![
+            (DocKind::Next, 0, 1, 2),
+            (DocKind::Previous, 2, 3, 2),
+            (DocKind::Section(1), 5, 5, 1),
+            (DocKind::Next, 7, 8, 2),
+            (DocKind::Next, 11, 11, 1),
+        ]);
+        assert_eq!(blocks[0].span,
+                   span::Span::new(span::Position::new(0, 0, 0),
+                                   span::Position::new(34, 1, 17)));
+    }
+}
@@ -1,8 +1,13 @@
+use crate::lexeme;
+use crate::limits;
+use crate::span;
+use crate::token;
+
 pub trait DFA {
 
     type State;
 
-    // some automata work only with ASCII characters todo why?
+    // what the automaton reads, usually a char
     type Input;
 
     fn advance(&mut self, input : Self::Input);
@@ -10,9 +15,114 @@ pub trait DFA {
     fn in_final_state(&self) -> bool;
 
     fn in_fail_state(&self) -> bool;
+
+    /// Tells if the automaton has seen the beginning of its lexeme, so
+    /// that running out of input now is an error rather than a mismatch.
+    fn is_incomplete(&self) -> bool { false }
+
+    /// Why the lexeme is broken, when the automaton failed after it had
+    /// started, or would fail if the input ended now.
+    fn error(&self) -> Option<lexeme::Error> {
+        if self.is_incomplete() {
+            Some(lexeme::Error::UnexpectedEndOfInput)
+        } else {
+            None
+        }
+    }
+
+    /// Takes the limits the automaton has to keep to, if any, such as how
+    /// deep comments may be nested.
+    fn set_limits(&mut self, _limits : &limits::LexerLimits) {}
+}
+
+/// A `DFA` that reads chars, with its state type hidden, so that
+/// different automata can be run side by side, as the combined automaton
+/// does.
+pub trait CharDFA {
+    /// Goes back to the initial state.
+    fn reset(&mut self);
+
+    fn advance_char(&mut self, input : char);
+
+    fn is_final(&self) -> bool;
+
+    fn is_failed(&self) -> bool;
+
+    fn is_incomplete(&self) -> bool;
+
+    fn error(&self) -> Option<lexeme::Error>;
+
+    fn set_limits(&mut self, limits : &limits::LexerLimits);
+}
+
+impl<T : DFA<Input = char> + Default> CharDFA for T {
+    fn reset(&mut self) {
+        *self = T::default()
+    }
+
+    fn advance_char(&mut self, input : char) {
+        self.advance(input)
+    }
+
+    fn is_final(&self) -> bool {
+        self.in_final_state()
+    }
+
+    fn is_failed(&self) -> bool {
+        self.in_fail_state()
+    }
+
+    fn is_incomplete(&self) -> bool {
+        DFA::is_incomplete(self)
+    }
+
+    fn error(&self) -> Option<lexeme::Error> {
+        DFA::error(self)
+    }
+
+    fn set_limits(&mut self, limits : &limits::LexerLimits) {
+        DFA::set_limits(self, limits)
+    }
+}
+
+/// Runs `dfa` until it accepts, and makes a token of `token_type` out of
+/// what it has read, which is how the hand-written automata are lexemes
+/// on their own. The span counts the lines of the token too, strings have
+/// them in their gaps.
+pub fn recognize_with<D : DFA<Input = char>>(mut dfa : D, token_type : token::TokenType,
+                                             input : &str)
+                                             -> Result<token::Token<'_>, lexeme::Error> {
+    // where the token ends so far, relative to its beginning
+    let mut end = span::Position::default();
+
+    for character in input.chars() {
+        end = end.advance_char(character);
+
+        dfa.advance(character);
+        if dfa.in_final_state() {
+            return Ok(token::Token::new(
+                token_type,
+                span::Span::new(span::Position::default(), end),
+                &input[..end.offset]))
+        } else if dfa.in_fail_state() {
+            return Err(dfa.error().unwrap_or(lexeme::Error::Automata(Error::IsInFailState)))
+        }
+    }
+
+    Err(dfa.error().unwrap_or(lexeme::Error::NotRecognized))
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Error {
     IsInFailState,
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::IsInFailState => write!(f, "the automaton rejected the input"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,455 @@
+//! Turns lexical errors into messages for humans: a stable error code,
+//! the span the error is about, secondary labels, and an excerpt of the
+//! source code with the spans underlined, like this
+//!
+//! ```text
+//! error[L0010]: unterminated block comment
+//!  --> 1:5
+//!   |
+//! 1 | x = {- y
+//!   |     ^^ the comment is opened here
+//!   |         - the file ends before the comment is closed
+//! ```
+
+use crate::driver;
+use crate::lexeme;
+use crate::span;
+use crate::string;
+use crate::token;
+
+use std::fmt;
+
+/// How the rendered diagnostics look.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Plain,
+    /// Uses ANSI escape sequences, for terminals.
+    Colored,
+}
+
+/// A span with a message explaining its role in the diagnostic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span : span::Span,
+    pub message : String,
+}
+
+impl Label {
+    pub fn new(span : span::Span, message : &str) -> Self {
+        Self { span, message : message.to_string() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub code : &'static str,
+    pub message : String,
+    /// What the error is about.
+    pub primary : Label,
+    /// Other places that help to understand the error.
+    pub secondary : Vec<Label>,
+}
+
+impl Diagnostic {
+
+    /// Explains an error that was found at `span` of `source`.
+    pub fn new(reason : &lexeme::Error, span : span::Span, source : &str) -> Self {
+        match reason {
+            lexeme::Error::UnexpectedEndOfInput => {
+                // the lexeme runs to the end of file, so point at both
+                let eof = span::Span::new(span.end, span.end);
+                let first = first_char(span, source);
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(first, "the lexeme starts here"),
+                    secondary : vec![Label::new(
+                        eof, "the file ends before the lexeme does")],
+                }
+            },
+            lexeme::Error::UnterminatedPragma => {
+                let eof = span::Span::new(span.end, span.end);
+                let opening = span::Span::new(span.start, span.start.advance("{-#"));
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(opening, "the pragma is opened here"),
+                    secondary : vec![Label::new(
+                        eof, "the file ends before the pragma is closed")],
+                }
+            },
+            lexeme::Error::UnterminatedBlockComment { outermost, nested, .. } => {
+                let eof = span::Span::new(span.end, span.end);
+                let opening = |start : span::Position| span::Span::new(start, start.advance("{-"));
+                let mut secondary : Vec<Label> = nested.iter()
+                    .map(|start| Label::new(opening(*start), "this one is not closed either"))
+                    .collect();
+                secondary.push(Label::new(eof, "the file ends before the comment is closed"));
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(opening(*outermost), "the comment is opened here"),
+                    secondary,
+                }
+            },
+            lexeme::Error::String(error) => {
+                let opening = first_char(span, source);
+                let end = span::Span::new(span.end, span.end);
+                let secondary = match error {
+                    string::Error::EndOfLine =>
+                        vec![Label::new(end, "the line ends before the string is closed")],
+                    string::Error::EndOfInput =>
+                        vec![Label::new(end, "the file ends before the string is closed")],
+                    _ => vec![Label::new(span, "in this string")],
+                };
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(opening, "the string is opened here"),
+                    secondary,
+                }
+            },
+            lexeme::Error::Literal(error) => {
+                let (start, end) = error.escape();
+                let literal = &source[span.start.offset..span.end.offset];
+                let escape_start = span.start.advance(&literal[..start]);
+                let escape = span::Span::new(escape_start,
+                                             escape_start.advance(&literal[start..end]));
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(escape, "this escape has no character"),
+                    secondary : vec![Label::new(span, "in this literal")],
+                }
+            },
+            lexeme::Error::CommentTooDeep { limit, at } => {
+                let opening = |start : span::Position| span::Span::new(start, start.advance("{-"));
+                // with a limit of 0, the outermost one is already too deep
+                let secondary = if *at == span.start {
+                    vec![]
+                } else {
+                    vec![Label::new(opening(span.start), "the outermost comment is opened here")]
+                };
+                Self {
+                    code : reason.code(),
+                    message : reason.to_string(),
+                    primary : Label::new(opening(*at),
+                                         &format!("this one is nested {} deep", limit + 1)),
+                    secondary,
+                }
+            },
+            lexeme::Error::TokenTooLong { limit } => Self {
+                code : reason.code(),
+                message : reason.to_string(),
+                primary : Label::new(span, &format!("this one goes on past {} bytes", limit)),
+                secondary : vec![],
+            },
+            lexeme::Error::TooManyTokens { limit } => Self {
+                code : reason.code(),
+                message : reason.to_string(),
+                primary : Label::new(first_char(span, source),
+                                     &format!("the lexer stops here, after {} tokens", limit)),
+                secondary : vec![],
+            },
+            lexeme::Error::InputTooLarge { .. } => Self {
+                code : reason.code(),
+                message : reason.to_string(),
+                // nothing is read, there is no better place than where it starts
+                primary : Label::new(first_char(span, source),
+                                     &format!("the file is not lexed at all, it has {} bytes",
+                                              source.len())),
+                secondary : vec![],
+            },
+            lexeme::Error::Cancelled => Self {
+                code : reason.code(),
+                message : reason.to_string(),
+                primary : Label::new(first_char(span, source),
+                                     "the lexer was cancelled before it got here"),
+                secondary : vec![],
+            },
+            _ => Self {
+                code : reason.code(),
+                message : reason.to_string(),
+                primary : Label::new(span, "no token can be made of this"),
+                secondary : vec![],
+            },
+        }
+    }
+
+    pub fn of_error(error : &driver::LexError, source : &str) -> Self {
+        Self::new(&error.reason, error.span, source)
+    }
+
+    /// Explains a `TokenType::Error` token, as produced by a recovering
+    /// lexer, other tokens have nothing to explain.
+    pub fn of_token(token : &token::Token<'_>, source : &str) -> Option<Self> {
+        token.error().map(|reason| Self::new(reason, token.span, source))
+    }
+
+    /// Renders the diagnostic with an excerpt of `source`, which must be
+    /// the source code that the spans refer to.
+    pub fn render(&self, source : &str, style : Style) -> String {
+        let paint = |color : &str, text : &str| match style {
+            Style::Plain => text.to_string(),
+            Style::Colored => format!("\x1b[{}m{}\x1b[0m", color, text),
+        };
+
+        let mut labels : Vec<(&Label, bool)> = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|label| (label, false)));
+        labels.sort_by_key(|(label, _)| label.span);
+
+        let last_line = labels.iter()
+            .map(|(label, _)| label.span.start.line + 1).max().unwrap_or(1);
+        let gutter = " ".repeat(last_line.to_string().len());
+        let lines : Vec<&str> = source.split('\n')
+            .map(|line| line.trim_end_matches('\r')).collect();
+
+        let mut out = String::new();
+        out += &format!("{}{}\n",
+                        paint("1;31", &format!("error[{}]", self.code)),
+                        paint("1", &format!(": {}", self.message)));
+        out += &format!("{}{} {}:{}\n", gutter, paint("1;34", "-->"),
+                        self.primary.span.start.line + 1,
+                        self.primary.span.start.column + 1);
+        out += &format!("{} {}\n", gutter, paint("1;34", "|"));
+
+        let mut shown_line = None;
+        for (label, is_primary) in labels {
+            let line_number = label.span.start.line;
+            let line = lines.get(line_number).copied().unwrap_or("");
+            if shown_line != Some(line_number) {
+                out += &format!("{} {}\n",
+                                paint("1;34", &format!("{:>width$} |", line_number + 1,
+                                                       width = gutter.len())),
+                                line);
+                shown_line = Some(line_number);
+            }
+
+            // keep the tabs, so that the marks line up with the text
+            let indent : String = line.chars().take(label.span.start.column)
+                .map(|character| if character == '\t' { '\t' } else { ' ' })
+                .collect();
+            let line_end = if label.span.end.line == line_number {
+                label.span.end.column
+            } else {
+                line.chars().count()
+            };
+            let width = std::cmp::max(1, line_end.saturating_sub(label.span.start.column));
+            let (mark, color) = if is_primary { ("^", "1;31") } else { ("-", "1;34") };
+            out += &format!("{} {} {}\n",
+                            gutter,
+                            paint("1;34", "|"),
+                            paint(color, &format!("{}{} {}", indent,
+                                                  mark.repeat(width), label.message)));
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error[{}]: {} at {}:{}", self.code, self.message,
+               self.primary.span.start.line + 1, self.primary.span.start.column + 1)
+    }
+}
+
+/// Lexes `source` to the end, and explains every error found on the way.
+pub fn check(source : &str) -> Vec<Diagnostic> {
+    driver::Lexer::recovering(source)
+        .filter_map(|token| token.ok())
+        .filter_map(|token| Diagnostic::of_token(&token, source))
+        .collect()
+}
+
+fn first_char(span : span::Span, source : &str) -> span::Span {
+    match source[span.start.offset..].chars().next() {
+        Some(character) => span::Span::new(span.start,
+                                           span.start.advance_char(character)),
+        None => span,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::limits;
+
+    #[test]
+    fn unterminated_comment() {
+        let source = "x = {- y\n  z";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "L0010");
+        assert_eq!(diagnostics[0].render(source, Style::Plain), "\
+error[L0010]: unterminated block comment
+ --> 1:5
+  |
+1 | x = {- y
+  |     ^^ the comment is opened here
+2 |   z
+  |    - the file ends before the comment is closed
+");
+    }
+
+    #[test]
+    fn unterminated_nested_comments() {
+        let source = "x = 1\n{- a {- b -}\n  {- c\n   {- d";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].render(source, Style::Plain), "\
+error[L0010]: unterminated block comment, 2 nested comments in it are not closed either
+ --> 2:1
+  |
+2 | {- a {- b -}
+  | ^^ the comment is opened here
+3 |   {- c
+  |   -- this one is not closed either
+4 |    {- d
+  |    -- this one is not closed either
+  |        - the file ends before the comment is closed
+");
+    }
+
+    #[test]
+    fn unterminated_pragma() {
+        let source = "{-# INLINE f -}
+f = 1";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].render(source, Style::Plain), "\
+error[L0016]: unterminated pragma
+ --> 1:1
+  |
+1 | {-# INLINE f -}
+  | ^^^ the pragma is opened here
+2 | f = 1
+  |      - the file ends before the pragma is closed
+");
+    }
+
+    #[test]
+    fn unrecognized() {
+        let source = "f = \u{200b}";
+        let diagnostic = Diagnostic::of_error(
+            &driver::Lexer::new(source).find_map(|token| token.err()).unwrap(),
+            source);
+        assert_eq!(diagnostic.to_string(), "error[L0001]: unrecognized input at 1:5");
+        assert_eq!(diagnostic.render(source, Style::Plain), "\
+error[L0001]: unrecognized input
+ --> 1:5
+  |
+1 | f = \u{200b}
+  |     ^ no token can be made of this
+");
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let source = "s = \"abc\nt = 1";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "L0008");
+        assert_eq!(diagnostics[0].render(source, Style::Plain), "\
+error[L0008]: unterminated string, the line ends before it does
+ --> 1:5
+  |
+1 | s = \"abc
+  |     ^ the string is opened here
+  |         - the line ends before the string is closed
+");
+    }
+
+    #[test]
+    fn escape_out_of_range() {
+        let source = "c = '\\1114112'";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].render(source, Style::Plain), "\
+error[L0009]: numeric escape sequence out of range, the last character is \\1114111
+ --> 1:6
+  |
+1 | c = '\\1114112'
+  |     ---------- in this literal
+  |      ^^^^^^^^ this escape has no character
+");
+    }
+
+    fn limited(source : &str, limits : limits::LexerLimits) -> String {
+        let error = driver::Lexer::new(source).with_limits(limits)
+            .find_map(|token| token.err()).unwrap();
+        Diagnostic::of_error(&error, source).render(source, Style::Plain)
+    }
+
+    #[test]
+    fn limits() {
+        let unlimited = limits::LexerLimits::unlimited();
+        assert_eq!(limited("x {- {- -} -} y",
+                           limits::LexerLimits { max_comment_nesting : 1, ..unlimited }), "\
+error[L0011]: comments are nested deeper than the limit of 1
+ --> 1:6
+  |
+1 | x {- {- -} -} y
+  |   -- the outermost comment is opened here
+  |      ^^ this one is nested 2 deep
+");
+        assert_eq!(limited("x = abcdef",
+                           limits::LexerLimits { max_token_length : 3, ..unlimited }), "\
+error[L0012]: the token is longer than 3 bytes
+ --> 1:5
+  |
+1 | x = abcdef
+  |     ^^^^^^ this one goes on past 3 bytes
+");
+        assert_eq!(limited("x = y",
+                           limits::LexerLimits { max_token_count : 2, ..unlimited }), "\
+error[L0013]: there are more than 2 tokens
+ --> 1:5
+  |
+1 | x = y
+  |     ^ the lexer stops here, after 2 tokens
+");
+        assert_eq!(limited("x = y",
+                           limits::LexerLimits { max_input_size : 4, ..unlimited }), "\
+error[L0014]: the input is larger than 4 bytes
+ --> 1:1
+  |
+1 | x = y
+  | ^ the file is not lexed at all, it has 5 bytes
+");
+    }
+
+    #[test]
+    fn cancelled() {
+        let source = "x = y";
+        let error = driver::Lexer::new(source).with_cancellation(&|| true)
+            .find_map(|token| token.err()).unwrap();
+        assert_eq!(Diagnostic::of_error(&error, source).render(source, Style::Plain), "\
+error[L0015]: lexing was cancelled
+ --> 1:1
+  |
+1 | x = y
+  | ^ the lexer was cancelled before it got here
+");
+    }
+
+    #[test]
+    fn colored() {
+        let source = "\t\u{200b}";
+        let rendered = check(source)[0].render(source, Style::Colored);
+        assert!(rendered.starts_with("\x1b[1;31merror[L0001]\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m\t^ no token can be made of this\x1b[0m"));
+    }
+
+    #[test]
+    fn errors_display() {
+        let error = driver::LexError {
+            span : span::Span::of("\u{200b}"),
+            reason : lexeme::Error::NotRecognized,
+        };
+        assert_eq!(error.to_string(), "unrecognized input at 1:1");
+        assert_eq!(lexeme::Error::Regex(crate::regex::Error::NoMatch).to_string(),
+                   "the expression does not match");
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -1,111 +1,837 @@
 use crate::*;
-use crate::lexeme::Lexeme;
+use crate::combined::{self, CombinedAutomaton, Definition};
 
-/// Generates Haskell token stream, uses a string view on the source code.
-/// Operates on the top-level lexemes. These '__lexemes__' are not implemented.
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Streams Haskell tokens out of a string view on the source code.
+/// Operates on the top-level lexemes.
 /// ---- Rules covered at this scope ----
 /// `program -> whitespace | lexeme`
 ///
 /// `whitespace -> whitestuff {whitestuff}
-/// `whitestuff -> whitechar | __comment__ | mlcomment
-/// `whitechar -> '\n' | '\r' | ' ' | '\t'
+/// `whitestuff -> whitechar | comment | mlcomment
+/// `whitechar -> newline | vertab | space | tab | uniWhite
 ///
 /// `lexeme -> qvarid | qconid | qvarsym | qconsym
-///          | __literal__ | special | reservedop | reservedid
-
-fn gen_hs_token_stream(string_view : &str) -> Vec<token::Token> {
-    // return value
-    let mut token_stream : Vec<token::Token> = vec![];
-    // how many characters away from the beginning of file
-    let mut buffer_offset : usize = 0;
-
-    // current position in the source buffer
-    let mut cur_row : u32 = 0;
-    let mut cur_col : u16 = 0;
-
-    // only handle basic whitestuff, the 2010 report names more characters (vertical
-    // tab, form feed, any unicode char that represents whitespace)
-    let whitechar = vec!['\n', '\r', '\t', ' '];
-
-    // these are single characters, makes sense to check them here
-    let special = vec!['|', ',', ';', '[', ']', '`', '{', '}'];
-
-    let lexemes : Vec<fn(&str) -> Result::<token::Token, lexeme::Error>> = vec![
-        // todo rethink the grammar one more time, esp. how identifiers exclude
-        // reserved ids and ops - seems okay but ?
-        mlcomment::MLComment::recognize,
-        reserved::ReservedId::recognize,
-        reserved::ReservedOp::recognize,
-        qident::QConId::recognize,
-        qident::QVarId::recognize,
-        qident::QVarSym::recognize,
-        qident::QConSym::recognize,
-    ];
-
-    while buffer_offset < string_view.len() {
-
-        // whitespace-like (non-comment)
-        if whitechar.contains(&string_view.chars().nth(buffer_offset).unwrap()) {
-            buffer_offset += 1;
-            continue
-        }
-
-        if special.contains(&string_view.chars().nth(buffer_offset).unwrap()) {
-            token_stream.push(token::Token { span : vec![1],
-                                             token_type : token::TokenType::Special
-            });
-            continue
-        }
-
-        for recognizer in &lexemes {
-            if let Ok(token) = recognizer(&string_view[buffer_offset..]) {
-                // todo maximal munch!
-                // todo no cast?
-                buffer_offset += token.span.iter()
-                    .fold(token.span.len() - 1, |sum, x| sum + *x as usize);
-                cur_row += token.span.len() as u32;
-                cur_col = *token.span.last().unwrap();
-                token_stream.push(token)
+///          | literal | special | reservedop | reservedid
+///
+/// Tokens are produced lazily, one per call to `next()`, so a parser can
+/// pull them as it goes and look ahead with `peek()` and `peek_nth()`.
+/// The lexer stops after the first error it reports, unless it was created
+/// with `Lexer::recovering()` or `Lexer::lossless()`. It always stops when
+/// it goes past its `limits::LexerLimits`, or when it is cancelled.
+pub struct Lexer<'a> {
+    string_view : &'a str,
+    // current position in the source buffer, the offset is how many bytes
+    // away from the beginning of file
+    position : span::Position,
+    // tokens that were produced by looking ahead, but not consumed yet
+    lookahead : VecDeque<Result<token::Token<'a>, LexError>>,
+    failed : bool,
+    // report errors as tokens and keep going
+    recover : bool,
+    // report whitespace as tokens instead of skipping it
+    keep_whitespace : bool,
+    // the automaton that finds the lexemes, `lexemes()` unless another
+    // one is given
+    lexemes : &'a dyn combined::Recognizer,
+    // reused by every token, so that lexing does not allocate
+    scratch : combined::Scratch,
+    limits : limits::LexerLimits,
+    // how many tokens were made so far
+    count : usize,
+    // asked before every token whether to stop
+    is_cancelled : Option<&'a dyn Fn() -> bool>,
+}
+
+/// A lexeme that could not be recognized, along with the span of the
+/// source code at which the lexer gave up.
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub span : span::Span,
+    pub reason : lexeme::Error,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.reason,
+               self.span.start.line + 1, self.span.start.column + 1)
+    }
+}
+
+impl std::error::Error for LexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.reason)
+    }
+}
+
+// these are single characters, makes sense to check them here, but only
+// after the recognizers, because '{' may as well open a comment
+const SPECIAL : [char; 9] = ['(', ')', ',', ';', '[', ']', '`', '{', '}'];
+
+/// All the lexemes are looked for at every position, and the longest match
+/// wins (maximal munch). When several of them match the same number of
+/// bytes, the one listed first wins, so the order here is the priority:
+/// - reservedid goes before qvarid, and reservedop before qvarsym and
+///   qconsym, as the report excludes them from identifiers and operators
+///   (it excludes dashes from qvarsym too, but that is up to qvarsym);
+/// - the rest never match the same text, their order does not matter,
+///   mlcomment leaves what starts with `{-#` to pragma.
+///
+/// `build.rs` and `haskell.lex` list them too, tests check that they
+/// agree.
+pub fn definitions() -> Vec<Definition> {
+    vec![
+        Definition::Automaton {
+            token_type : token::TokenType::MLComment,
+            start : || Box::new(mlcomment::MLComment::new()),
+        },
+        Definition::of::<reserved::ReservedId>(),
+        Definition::of::<reserved::ReservedOp>(),
+        Definition::of::<qident::QConId>(),
+        Definition::of::<qident::QVarId>(),
+        Definition::of::<qident::QVarSym>(),
+        Definition::of::<qident::QConSym>(),
+        Definition::of::<literals::Integer>(),
+        Definition::of::<literals::Float>(),
+        Definition::of::<literals::Char>(),
+        Definition::Automaton {
+            token_type : token::TokenType::StringLiteral,
+            start : || Box::new(string::StringLiteral::new()),
+        },
+        Definition::of::<comment::SLComment>(),
+        Definition::Automaton {
+            token_type : token::TokenType::Pragma,
+            start : || Box::new(pragma::Pragma::new()),
+        },
+    ]
+}
+
+/// The automaton of `definitions()`, read from the tables that are
+/// generated at build time, when the first lexer is made.
+fn lexemes() -> &'static CombinedAutomaton<&'static [u32]> {
+    static LEXEMES : once_cell::sync::OnceCell<CombinedAutomaton<&'static [u32]>> =
+        once_cell::sync::OnceCell::new();
+    LEXEMES.get_or_init(|| match CombinedAutomaton::from_tables(definitions(),
+                                                                tables::dfa()) {
+        Ok(automaton) => automaton,
+        Err(error) => panic!("the lexer tables are broken: {}", error),
+    })
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(string_view : &'a str) -> Self {
+        Self {
+            string_view,
+            position : span::Position::default(),
+            lookahead : VecDeque::new(),
+            failed : false,
+            recover : false,
+            keep_whitespace : false,
+            lexemes : lexemes(),
+            scratch : lexemes().scratch(),
+            limits : limits::LexerLimits::default(),
+            count : 0,
+            is_cancelled : None,
+        }
+    }
+
+    /// The same lexer, but with other lexemes, such as the ones of a
+    /// `spec::Spec`, instead of the Haskell ones.
+    pub fn with_lexemes(self, lexemes : &'a dyn combined::Recognizer) -> Self {
+        let mut scratch = lexemes.scratch();
+        scratch.set_limits(self.limits);
+        Self { lexemes, scratch, ..self }
+    }
+
+    /// The same lexer, but it reports an error and stops when it goes past
+    /// one of `limits`.
+    pub fn with_limits(mut self, limits : limits::LexerLimits) -> Self {
+        self.scratch.set_limits(limits);
+        Self { limits, ..self }
+    }
+
+    /// The same lexer, but it asks `is_cancelled` before every token, and
+    /// reports `lexeme::Error::Cancelled` and stops once it is true, so
+    /// that an editor can give up on a file that changed meanwhile. It is
+    /// not asked while a token is read, so a long one, like a comment that
+    /// is never closed, is read to its end first; `with_limits()` bounds
+    /// how long that takes.
+    pub fn with_cancellation(self, is_cancelled : &'a dyn Fn() -> bool) -> Self {
+        Self { is_cancelled : Some(is_cancelled), ..self }
+    }
+
+    /// A lexer that never yields `Err`, it reports what it cannot lex as
+    /// `TokenType::Error` tokens instead, and goes on from the next place
+    /// where a token may start. Meant for editors and linters, which want
+    /// to see every problem in a file at once.
+    pub fn recovering(string_view : &'a str) -> Self {
+        Self { recover : true, ..Self::new(string_view) }
+    }
+
+    /// A recovering lexer that starts at `position` of `string_view`,
+    /// which has to be a place where a token may start.
+    pub(crate) fn recovering_from(string_view : &'a str, position : span::Position) -> Self {
+        Self { position, ..Self::recovering(string_view) }
+    }
+
+    /// A recovering lexer that reports whitespace too, as `Whitespace` and
+    /// `Newline` tokens, so that every byte of the input belongs to exactly
+    /// one token. `trivia::tokenize` attaches them to their neighbours.
+    pub fn lossless(string_view : &'a str) -> Self {
+        Self { keep_whitespace : true, ..Self::recovering(string_view) }
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&Result<token::Token<'a>, LexError>> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the token `n` positions ahead without consuming anything,
+    /// `peek_nth(0)` is the same as `peek()`.
+    pub fn peek_nth(&mut self, n : usize) -> Option<&Result<token::Token<'a>, LexError>> {
+        while self.lookahead.len() <= n {
+            match self.lex_next() {
+                Some(item) => self.lookahead.push_back(item),
+                None => return None,
+            }
+        }
+        self.lookahead.get(n)
+    }
+
+    fn lex_next(&mut self) -> Option<Result<token::Token<'a>, LexError>> {
+        if self.failed {
+            return None
+        }
+        if self.string_view.len() > self.limits.max_input_size {
+            // not a byte of it is read, not even to find where it ends
+            let limit = self.limits.max_input_size;
+            return self.stop(span::Span::new(self.position, self.position),
+                             lexeme::Error::InputTooLarge { limit })
+        }
+        if self.is_cancelled.is_some_and(|is_cancelled| is_cancelled()) {
+            return self.stop(self.rest_from(self.position), lexeme::Error::Cancelled)
+        }
+
+        let item = self.lex_token()?;
+        if self.count == self.limits.max_token_count {
+            let start = match &item {
+                Ok(token) => token.span.start,
+                Err(error) => error.span.start,
+            };
+            let limit = self.limits.max_token_count;
+            return self.stop(self.rest_from(start), lexeme::Error::TooManyTokens { limit })
+        }
+        self.count += 1;
+        Some(item)
+    }
+
+    fn lex_token(&mut self) -> Option<Result<token::Token<'a>, LexError>> {
+        let rest = &self.string_view[self.position.offset..];
+
+        if self.keep_whitespace {
+            if let Some(mut token) = whitespace(rest) {
+                token.span = token.span.shifted(self.position);
+                self.position = token.span.end;
+                return Some(Ok(token))
+            }
+        }
+
+        let rest_trimmed = rest.trim_start_matches(reused::is_whitechar);
+        self.position = self.position.advance(
+            &rest[..rest.len() - rest_trimmed.len()]);
+
+        if rest_trimmed.is_empty() {
+            return None
+        }
+
+        match recognize(rest_trimmed, self.lexemes, &mut self.scratch) {
+            Ok(mut token) => {
+                token.span = token.span.shifted(self.position);
+                self.position = token.span.end;
+                if let Some(kind) = comment::doc_kind(token.token_type, token.text) {
+                    token.payload = Some(token::Payload::Doc(kind));
+                }
+                if let Some(contents) = pragma::contents(token.token_type, token.text) {
+                    token.payload = Some(token::Payload::Pragma(contents));
+                }
+                match value::decode(token.token_type, token.text) {
+                    None => Some(Ok(token)),
+                    Some(Ok(value)) => {
+                        token.payload = Some(token::Payload::Literal(value));
+                        Some(Ok(token))
+                    },
+                    Some(Err(error)) => self.report(token.span, lexeme::Error::Literal(error)),
+                }
+            },
+            Err(reason) => {
+                // a lexeme that runs to the end of file takes all of it,
+                // otherwise skip to where the next token may start
+                let end = match reason {
+                    lexeme::Error::UnexpectedEndOfInput
+                        | lexeme::Error::UnterminatedPragma
+                        | lexeme::Error::UnterminatedBlockComment { .. }
+                        | lexeme::Error::CommentTooDeep { .. } =>
+                        self.position.advance(rest_trimmed),
+                    // looking for where a token starts would read past the
+                    // limit again and again, whitespace is found in one go
+                    lexeme::Error::TokenTooLong { .. } => {
+                        let first = rest_trimmed.chars().next().map_or(0, char::len_utf8);
+                        let len = rest_trimmed[first..].find(reused::is_whitechar)
+                            .map_or(rest_trimmed.len(), |len| first + len);
+                        self.position.advance(&rest_trimmed[..len])
+                    },
+                    // the closing quote of a broken string would open
+                    // another one, so the string is skipped as a whole
+                    lexeme::Error::String(_) => self.position.advance(
+                        &rest_trimmed[..string::extent(rest_trimmed)]),
+                    _ => self.position.advance(
+                        resynchronize(rest_trimmed, self.lexemes, &mut self.scratch)),
+                };
+                let span = span::Span::new(self.position, end);
+                let reason = reason.shifted(self.position);
+                self.position = end;
+                self.report(span, reason)
+            },
+        }
+    }
+
+    /// The span of the input from `start` on.
+    fn rest_from(&self, start : span::Position) -> span::Span {
+        span::Span::new(start, start.advance(&self.string_view[start.offset..]))
+    }
+
+    /// Reports the error at `span`, and stops, even when recovering.
+    fn stop(&mut self, span : span::Span, reason : lexeme::Error)
+            -> Option<Result<token::Token<'a>, LexError>> {
+        self.position = span.end;
+        let item = self.report(span, reason);
+        self.failed = true;
+        item
+    }
+
+    /// Reports the error at `span`, as an `Error` token when recovering.
+    fn report(&mut self, span : span::Span, reason : lexeme::Error)
+              -> Option<Result<token::Token<'a>, LexError>> {
+        if self.recover {
+            Some(Ok(token::Token {
+                span,
+                token_type : token::TokenType::Error,
+                text : &self.string_view[span.start.offset..span.end.offset],
+                payload : Some(token::Payload::Error(reason)),
+            }))
+        } else {
+            self.failed = true;
+            Some(Err(LexError { span, reason }))
+        }
+    }
+}
+
+/// newline -> return linefeed | return | linefeed | formfeed
+///
+/// Recognizes one newline, or else a run of other whitechars, at the
+/// beginning of `input`.
+fn whitespace(input : &str) -> Option<token::Token<'_>> {
+    let newline = if input.starts_with("\r\n") {
+        2
+    } else if input.starts_with(&['\r', '\n', '\x0C'][..]) {
+        1
+    } else {
+        0
+    };
+    if newline > 0 {
+        return Some(token::Token::new(token::TokenType::Newline,
+                                      span::Span::of(&input[..newline]),
+                                      &input[..newline]))
+    }
+
+    let rest = input.trim_start_matches(
+        |character| reused::is_whitechar(character)
+            && !matches!(character, '\r' | '\n' | '\x0C'));
+    if rest.len() < input.len() {
+        let text = &input[..input.len() - rest.len()];
+        Some(token::Token::new(token::TokenType::Whitespace, span::Span::of(text), text))
+    } else {
+        None
+    }
+}
+
+/// Finds the longest lexeme at the beginning of `input`, its span is
+/// relative to `input`. When there is none, reports the error of a lexeme
+/// that started but went wrong, if any.
+fn recognize<'i>(input : &'i str, lexemes : &dyn combined::Recognizer,
+                 scratch : &mut combined::Scratch)
+                 -> Result<token::Token<'i>, lexeme::Error> {
+    let current = match input.chars().next() {
+        Some(current) => current,
+        None => return Err(lexeme::Error::NotRecognized),
+    };
+
+    match lexemes.longest_match_with(input, scratch) {
+        Ok(found) => {
+            let text = &input[..found.len];
+            Ok(token::Token::new(found.token_type, span::Span::of(text), text))
+        },
+        // '{' may be special, but not when it opens a broken comment
+        Err(reason) if !reason.is_mismatch() => Err(reason),
+        Err(reason) => {
+            if SPECIAL.contains(&current) {
+                let text = &input[..current.len_utf8()];
+                Ok(token::Token::new(token::TokenType::Special, span::Span::of(text), text))
             } else {
-                println!("No token recognized")
+                Err(reason)
             }
+        },
+    }
+}
+
+/// The part of `input` that has to be skipped after an error: at least
+/// one character, and then everything up to whitespace, a special
+/// character, or anything where a lexeme starts, even a broken one, which
+/// is reported on its own. Stopping at broken lexemes also keeps this
+/// linear, as an unterminated one is looked at only once.
+fn resynchronize<'i>(input : &'i str, lexemes : &dyn combined::Recognizer,
+                     scratch : &mut combined::Scratch) -> &'i str {
+    let mut chars = input.char_indices();
+    chars.next();
+    for (offset, character) in chars {
+        if reused::is_whitechar(character)
+            || SPECIAL.contains(&character)
+            || !matches!(recognize(&input[offset..], lexemes, scratch),
+                         Err(reason) if reason.is_mismatch()) {
+            return &input[..offset]
         }
     }
+    input
+}
 
-    token_stream
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<token::Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lookahead.pop_front() {
+            Some(item) => Some(item),
+            None => self.lex_next(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    // TODO need to test for EVERY LEXEME IN SEPARATION !!! THERE ARE BUGS ALREADY,
     // AND THERE WILL BE MORE
 
-    /* #[test]
+    fn types(input : &str) -> Vec<token::TokenType> {
+        Lexer::new(input).map(|token| token.unwrap().token_type).collect()
+    }
+
+    #[test]
     fn mlcomment() {
-        assert_eq!(gen_hs_token_stream("{--}"), vec![
-            token::Token
-            {
-                span : vec![4],
-                token_type : token::TokenType::MLComment
-            }]);
+        assert_eq!(Lexer::new("{--}").collect::<Vec<_>>(), vec![
+            Ok(token::Token
+               {
+                   span : span::Span::of("{--}"),
+                   token_type : token::TokenType::MLComment,
+                   text : "{--}",
+                   payload : None,
+               })]);
+    }
+
+    #[test]
+    fn pragmas() {
+        use token::TokenType::*;
+        let source = "{-# LANGUAGE GADTs #-}\nmodule M where\n{- {-# x #-} -}\n\
+                      f :: Int -> Int\n{-# inline f #-}";
+        let tokens = Lexer::new(source).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(tokens.iter().map(|token| token.token_type).collect::<Vec<_>>(),
+                   vec![Pragma, ReservedId, QConId, ReservedId, MLComment,
+                        QVarId, ReservedOp, QConId, ReservedOp, QConId, Pragma]);
+        assert_eq!(tokens[0].pragma(), Some(&pragma::Contents::Known {
+            name : pragma::Name::Language,
+            arguments : vec!["GADTs".to_string()],
+        }));
+        assert_eq!(tokens[10].pragma(), Some(&pragma::Contents::Known {
+            name : pragma::Name::Inline,
+            arguments : vec!["f".to_string()],
+        }));
+        assert_eq!(tokens[4].pragma(), None);
     }
 
     #[test]
     fn qconid() {
-        assert_eq!(gen_hs_token_stream("F.F"), vec![
-            token::Token
-            {
-                span : vec![3],
-                token_type : token::TokenType::QConId,
-            }]);
+        assert_eq!(Lexer::new("F.F").collect::<Vec<_>>(), vec![
+            Ok(token::Token
+               {
+                   span : span::Span::of("F.F"),
+                   token_type : token::TokenType::QConId,
+                   text : "F.F",
+                   payload : None,
+               })]);
+    }
+
+    #[test]
+    fn whitespace_and_special() {
+        assert_eq!(types(" import\tData.List\n( ;"), vec![
+            token::TokenType::ReservedId,
+            token::TokenType::QConId,
+            token::TokenType::Special,
+            token::TokenType::Special,
+        ]);
+    }
+
+    #[test]
+    fn maximal_munch() {
+        use token::TokenType::*;
+        assert_eq!(types("import"), vec![ReservedId]);
+        assert_eq!(types("imports"), vec![QVarId]);
+        assert_eq!(types("infixl infix in instance"),
+                   vec![ReservedId, ReservedId, ReservedId, ReservedId]);
+        assert_eq!(types("_ _x x'"), vec![ReservedId, QVarId, QVarId]);
+        assert_eq!(types(":: : ::: :+"),
+                   vec![ReservedOp, ReservedOp, QConSym, QConSym]);
+        assert_eq!(types("-> --> = == =>"),
+                   vec![ReservedOp, QVarSym, ReservedOp, QVarSym, ReservedOp]);
+        assert_eq!(types("x |-- y -- z\ny --- |\n---x"),
+                   vec![QVarId, QVarSym, QVarId, SLComment, QVarId, SLComment, SLComment]);
+        assert_eq!(types("x--y\n--\n:--"), vec![QVarId, SLComment, SLComment, QConSym]);
+        assert_eq!(types("M.x M.X M.+ M.:+"),
+                   vec![QVarId, QConId, QVarSym, QConSym]);
+        assert_eq!(types("f.g F.g f.. F.."),
+                   vec![QVarId, QVarSym, QVarId, // f . g
+                        QVarId, // F.g
+                        QVarId, ReservedOp, // f ..
+                        QVarSym]); // F..
+        // reserved words and operators are not qualified names, the longest
+        // lexeme is the module name
+        assert_eq!(types("M.where M.-> M.::"),
+                   vec![QConId, QVarSym, ReservedId, // M . where
+                        QConId, QVarSym, // M .->
+                        QConId, QVarSym]); // M .::
+    }
+
+    #[test]
+    fn one_token_per_position() {
+        let tokens : Vec<_> = Lexer::new("import qualified Data.Map as M (lookup, (!))")
+            .map(|token| token.unwrap()).collect();
+        assert_eq!(tokens.len(), 12);
+    }
+
+    #[test]
+    fn integer_literals() {
+        use token::TokenType::*;
+        assert_eq!(types("0x1F"), vec![IntegerLiteral]);
+        assert_eq!(types("f 0o17 42"), vec![QVarId, IntegerLiteral, IntegerLiteral]);
+        assert_eq!(types("0xG x1"), vec![IntegerLiteral, QVarId, QVarId]);
+        assert_eq!(types("M.x1+1"), vec![QVarId, QVarSym, IntegerLiteral]);
+    }
+
+    #[test]
+    fn float_literals() {
+        use token::TokenType::*;
+        assert_eq!(types("3.14 1e10 6.02E+23"), vec![FloatLiteral, FloatLiteral, FloatLiteral]);
+        assert_eq!(types("1.e5"), vec![IntegerLiteral, QVarSym, QVarId]);
+        assert_eq!(types("1..10"), vec![IntegerLiteral, ReservedOp, IntegerLiteral]);
+        assert_eq!(types("1.0e"), vec![FloatLiteral, QVarId]);
+        assert_eq!(types("1.0e-x"), vec![FloatLiteral, QVarId, QVarSym, QVarId]);
+    }
+
+    #[test]
+    fn char_literals() {
+        use token::TokenType::*;
+        assert_eq!(types("f 'x' '\\n' '\\^C' '\\DEL'"),
+                   vec![QVarId, CharLiteral, CharLiteral, CharLiteral, CharLiteral]);
+        // primes belong to identifiers
+        assert_eq!(types("x' x''"), vec![QVarId, QVarId]);
+        assert_eq!(types("f' 'a'"), vec![QVarId, CharLiteral]);
+        assert_eq!(types("x'a'"), vec![QVarId]);
+        assert_eq!(types("['a',' ']"),
+                   vec![Special, CharLiteral, Special, CharLiteral, Special]);
+    }
+
+    #[test]
+    fn string_literals() {
+        use token::TokenType::*;
+        assert_eq!(types("f \"x\" \"\\SOH\\&\\1234\\\"\" \"\""),
+                   vec![QVarId, StringLiteral, StringLiteral, StringLiteral]);
+
+        // a gap runs over lines, and the tokens after it are where they are
+        let tokens : Vec<_> = Lexer::new("s = \"ab\\\n    \\cd\" x")
+            .map(|token| token.unwrap()).collect();
+        assert_eq!(tokens[2].token_type, StringLiteral);
+        assert_eq!(tokens[2].line_spans().len(), 2);
+        assert_eq!(tokens[3].span.start, span::Position::new(18, 1, 9));
+    }
+
+    #[test]
+    fn literal_values() {
+        use value::LiteralValue;
+        let values : Vec<_> = Lexer::new("f 0x10 2.5 'a' \"b\\nc\"")
+            .map(|token| token.unwrap().literal().cloned())
+            .collect();
+        assert_eq!(values, vec![
+            None,
+            Some(LiteralValue::Integer(16u32.into())),
+            Some(LiteralValue::Float { mantissa : 25u32.into(), exponent : (-1).into() }),
+            Some(LiteralValue::Char('a')),
+            Some(LiteralValue::String("b\nc".to_string())),
+        ]);
+
+        // a well-formed literal without a value is an error
+        let mut lexer = Lexer::recovering("'\\1114112' x");
+        let token = lexer.next().unwrap().unwrap();
+        assert_eq!(token.token_type, token::TokenType::Error);
+        assert_eq!(token.text, "'\\1114112'");
+        assert_eq!(token.error(), Some(&lexeme::Error::Literal(
+            value::Error::OutOfRange { start : 1, end : 9 })));
+        assert_eq!(lexer.next().unwrap().unwrap().token_type, token::TokenType::QVarId);
+        // a surrogate is a Haskell character, the literal just has no value
+        let token = Lexer::new("\"\\xD800\"").next().unwrap().unwrap();
+        assert_eq!(token.token_type, token::TokenType::StringLiteral);
+        assert_eq!(token.payload, None);
+    }
+
+    #[test]
+    fn broken_strings() {
+        fn errors(input : &str) -> Vec<(lexeme::Error, &str)> {
+            Lexer::recovering(input)
+                .map(|token| token.unwrap())
+                .filter_map(|token| token.error().map(|error| (error.clone(), token.text)))
+                .collect()
+        }
+        use string::Error::*;
+        assert_eq!(errors("x = \"abc\ny"),
+                   vec![(lexeme::Error::String(EndOfLine), "\"abc")]);
+        assert_eq!(errors("x = \"a\\qb\" \"c\""),
+                   vec![(lexeme::Error::String(InvalidEscape), "\"a\\qb\"")]);
+        assert_eq!(errors("x = \"abc\\\n  "),
+                   vec![(lexeme::Error::String(EndOfInput), "\"abc\\\n  ")]);
+        assert_eq!(errors("\"a\\ b\" y"),
+                   vec![(lexeme::Error::String(UnterminatedGap), "\"a\\ b\"")]);
+    }
+
+    #[test]
+    fn spans() {
+        let spans : Vec<_> = Lexer::new("module M where\n  {- a\n -} x")
+            .map(|token| token.unwrap().span).collect();
+        assert_eq!(spans, vec![
+            span::Span::new(span::Position::new(0, 0, 0), span::Position::new(6, 0, 6)),
+            span::Span::new(span::Position::new(7, 0, 7), span::Position::new(8, 0, 8)),
+            span::Span::new(span::Position::new(9, 0, 9), span::Position::new(14, 0, 14)),
+            span::Span::new(span::Position::new(17, 1, 2), span::Position::new(25, 2, 3)),
+            span::Span::new(span::Position::new(26, 2, 4), span::Position::new(27, 2, 5)),
+        ]);
+    }
+
+    #[test]
+    fn unicode() {
+        use token::TokenType::*;
+        assert_eq!(types("f\u{a0}\u{3bb}x\u{3000}=\u{3bb}\u{2192}\u{3a3}.\u{3b1} {- \u{e9}t\u{e9} -}"),
+                   vec![QVarId, QVarId, ReservedOp, QVarId, QVarSym, QVarId, MLComment]);
+        assert_eq!(types("\u{3bb}\u{2237}\u{3a3}"), vec![QVarId, QVarSym, QConId]);
+    }
+
+    #[test]
+    fn peek() {
+        let mut lexer = Lexer::new("where x");
+        assert_eq!(lexer.peek_nth(1).unwrap().as_ref().unwrap().token_type,
+                   token::TokenType::QVarId);
+        assert_eq!(lexer.peek().unwrap().as_ref().unwrap().token_type,
+                   token::TokenType::ReservedId);
+        assert_eq!(lexer.next().unwrap().unwrap().token_type,
+                   token::TokenType::ReservedId);
+        assert_eq!(lexer.next().unwrap().unwrap().token_type,
+                   token::TokenType::QVarId);
+        assert_eq!(lexer.peek_nth(3), None);
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn stops_after_error() {
+        let mut lexer = Lexer::new("x \u{200b} y");
+        assert!(lexer.next().unwrap().is_ok());
+        assert_eq!(lexer.next(), Some(Err(LexError {
+            span : span::Span::new(span::Position::new(2, 0, 2),
+                                   span::Position::new(5, 0, 3)),
+            reason : lexeme::Error::NotRecognized })));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn recovering() {
+        use token::TokenType::*;
+        let tokens : Vec<_> = Lexer::recovering("x \u{200b}\u{200c} y \"abc\n` {- z")
+            .map(|token| token.unwrap()).collect();
+        assert_eq!(tokens.iter().map(|token| token.token_type).collect::<Vec<_>>(),
+                   vec![QVarId, Error, QVarId, Error, Special, Error]);
+
+        // the whole run of bad characters is one error
+        assert_eq!(tokens[1].span,
+                   span::Span::new(span::Position::new(2, 0, 2),
+                                   span::Position::new(8, 0, 4)));
+        assert_eq!(tokens[1].error(), Some(&lexeme::Error::NotRecognized));
+
+        // an unterminated string takes the rest of the line
+        assert_eq!(tokens[3].span,
+                   span::Span::new(span::Position::new(11, 0, 7),
+                                   span::Position::new(15, 0, 11)));
+        assert_eq!(tokens[3].error(),
+                   Some(&lexeme::Error::String(string::Error::EndOfLine)));
+
+        // an unterminated comment takes the rest of the file
+        assert_eq!(tokens[5].span,
+                   span::Span::new(span::Position::new(18, 1, 2),
+                                   span::Position::new(22, 1, 6)));
+        assert_eq!(tokens[5].error(),
+                   Some(&lexeme::Error::UnterminatedBlockComment {
+                       outermost : span::Position::new(18, 1, 2),
+                       nested : vec![],
+                       depth : 1,
+                   }));
+    }
+
+    #[test]
+    fn limits() {
+        fn limited(limits : limits::LexerLimits, input : &str)
+                   -> Vec<(&str, Option<lexeme::Error>)> {
+            Lexer::recovering(input).with_limits(limits)
+                .map(|token| {
+                    let token = token.unwrap();
+                    (token.text, token.error().cloned())
+                })
+                .collect()
+        }
+        let unlimited = limits::LexerLimits::default();
+
+        assert_eq!(limited(limits::LexerLimits { max_token_count : 2, ..unlimited },
+                           "x = y z"),
+                   vec![("x", None), ("=", None),
+                        ("y z", Some(lexeme::Error::TooManyTokens { limit : 2 }))]);
+        assert_eq!(limited(limits::LexerLimits { max_input_size : 4, ..unlimited },
+                           "x = y"),
+                   vec![("", Some(lexeme::Error::InputTooLarge { limit : 4 }))]);
+
+        // the rest of the long token is skipped, up to whitespace
+        assert_eq!(limited(limits::LexerLimits { max_token_length : 3, ..unlimited },
+                           "abc abcdef.g h"),
+                   vec![("abc", None),
+                        ("abcdef.g", Some(lexeme::Error::TokenTooLong { limit : 3 })),
+                        ("h", None)]);
+
+        // the position is where the comment is opened once too many
+        assert_eq!(limited(limits::LexerLimits { max_comment_nesting : 1, ..unlimited },
+                           "x {- {- -} -} y"),
+                   vec![("x", None),
+                        ("{- {- -} -} y", Some(lexeme::Error::CommentTooDeep {
+                            limit : 1,
+                            at : span::Position::new(5, 0, 5),
+                        }))]);
+
+        // the limits apply to the lexemes of a specification too
+        let automaton = spec::Spec::parse(include_str!("haskell.lex"))
+            .unwrap().automaton().unwrap();
+        let tokens : Vec<_> = Lexer::new("{- {- -} -}")
+            .with_limits(limits::LexerLimits { max_comment_nesting : 1, ..unlimited })
+            .with_lexemes(&automaton)
+            .collect();
+        assert!(matches!(tokens[..],
+                         [Err(LexError { reason : lexeme::Error::CommentTooDeep { .. }, .. })]));
+    }
+
+    #[test]
+    fn cancellation() {
+        let lexed = std::cell::Cell::new(0);
+        let is_cancelled = || lexed.get() == 2;
+        let mut tokens = vec![];
+        for token in Lexer::new("a b c d").with_cancellation(&is_cancelled) {
+            tokens.push(token);
+            lexed.set(lexed.get() + 1);
+        }
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[2], Err(LexError {
+            span : span::Span::new(span::Position::new(3, 0, 3),
+                                   span::Position::new(7, 0, 7)),
+            reason : lexeme::Error::Cancelled,
+        }));
+    }
+
+    #[test]
+    fn recovering_reports_every_problem() {
+        let errors = Lexer::recovering("a ? \"\n\"\nb \"")
+            .filter(|token| token.as_ref().unwrap().token_type == token::TokenType::Error)
+            .count();
+        assert_eq!(errors, 3);
+    }
+
+    /// A generated module of `lines` lines, with some multi-byte
+    /// characters and an error on every line.
+    fn generated_module(lines : usize) -> String {
+        let mut source = String::from("module Generated where\n");
+        for line in 0..lines {
+            source += &format!("f{} x = g (M.h x + \u{3bb}y) {{- \u{e9} -}} `div` y \u{200b} \u{2192}\n",
+                               line);
+        }
+        source
+    }
+
+    #[test]
+    fn generated_module_is_lexed_to_the_end() {
+        let source = generated_module(3);
+        let tokens : Vec<_> = Lexer::recovering(&source)
+            .map(|token| token.unwrap()).collect();
+        assert_eq!(tokens.last().unwrap().span.end,
+                   span::Position::default().advance(source.trim_end()));
+        assert_eq!(tokens.iter()
+                   .filter(|token| token.token_type == token::TokenType::Error)
+                   .count(), 3);
+    }
+
+    /// Lexing has to grow linearly with the size of the input: the
+    /// automata read every character a bounded number of times, whatever
+    /// the size of the module.
+    #[test]
+    fn linear() {
+        let read = |source : &str| {
+            let mut lexer = Lexer::recovering(source);
+            assert!(lexer.by_ref().all(|token| token.is_ok()));
+            lexer.scratch.read
+        };
+        for lines in [10, 100, 1000] {
+            let source = generated_module(lines);
+            let characters = source.chars().count();
+            let read = read(&source);
+            assert!(read < 2 * characters, "{} characters read for {}", read, characters);
+        }
+    }
+
+    /// A module of a megabyte and more takes less than a second in a
+    /// release build, and a debug build keeps to a generous bound. Time
+    /// depends on the machine, so it only runs when asked for, as in
+    /// `cargo test --release -- --ignored throughput`.
+    #[test]
+    #[ignore]
+    fn throughput() {
+        let time = |source : &str| {
+            let start = std::time::Instant::now();
+            assert!(Lexer::recovering(source).all(|token| token.is_ok()));
+            start.elapsed()
+        };
+        let small = generated_module(5000);
+        let large = generated_module(20000);
+        let (small_time, large_time) = (time(&small), time(&large));
+        // four times the input, and some slack for noise
+        assert!(large_time < small_time * 8,
+                "{:?} for {} bytes, {:?} for {} bytes",
+                small_time, small.len(), large_time, large.len());
+        assert!(large.len() > 1_000_000);
+        let bound = if cfg!(debug_assertions) { 15 } else { 1 };
+        assert!(large_time < std::time::Duration::from_secs(bound), "{:?}", large_time);
     }
-     */
 
     #[test]
     fn run() {
-        let raw = std::format!(r"{modid}", modid = "value");
+        let raw = r"value".to_string();
         assert_eq!(raw, "value");
     }
 }
@@ -0,0 +1,276 @@
+//! Re-lexing after a text edit, for editors that cannot afford to lex the
+//! whole file on every keystroke.
+//!
+//! Lexing restarts a little before the edit, and goes on until it produces
+//! a token that the old stream has too, at the same place once the edit is
+//! accounted for. From there on the old tokens are reused, only moved. An
+//! edit that opens or closes a comment changes how the rest of the file is
+//! lexed, so lexing simply goes on for longer then, possibly to the end.
+
+use crate::driver;
+use crate::span;
+use crate::token;
+
+use std::ops::Range;
+
+/// Replaces the bytes `range` of a text with `replacement`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub range : Range<usize>,
+    pub replacement : String,
+}
+
+impl TextEdit {
+    pub fn new(range : Range<usize>, replacement : &str) -> Self {
+        Self { range, replacement : replacement.to_string() }
+    }
+
+    /// The text after the edit.
+    pub fn apply(&self, text : &str) -> String {
+        let mut edited = String::with_capacity(
+            text.len() - self.range.len() + self.replacement.len());
+        edited += &text[..self.range.start];
+        edited += &self.replacement;
+        edited += &text[self.range.end..];
+        edited
+    }
+}
+
+/// The token stream after an edit, and what changed in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relexed<'src> {
+    pub tokens : Vec<token::Token<'src>>,
+    /// The old tokens that were replaced, as indices into the old stream.
+    pub old_range : Range<usize>,
+    /// What replaced them, as indices into `tokens`. The tokens after
+    /// `new_range` are the old tokens after `old_range`, which may have
+    /// moved, but did not change otherwise.
+    pub new_range : Range<usize>,
+}
+
+/// Re-lexes `new_source`, which is the source of `old_tokens` after `edit`.
+/// The old tokens must come from a `Lexer::recovering()`, and so do the
+/// new ones.
+pub fn relex<'src>(old_tokens : &[token::Token<'_>], edit : &TextEdit,
+                   new_source : &'src str) -> Relexed<'src> {
+    // the tokens that end before the edit are safe, unless maximal munch
+    // looked past them into the edit, as `'\111 x` does for the `'` that
+    // would close it. No lexeme reads past a newline without its token, or
+    // its error, running over it, so lexing restarts on the line of the
+    // edit, or before that, at the token that runs over the newline. The
+    // end of the token before is where the lexer got to from scratch too
+    let line_start = new_source[..edit.range.start]
+        .rfind(&['\n', '\r', '\x0C'][..])
+        .map_or(0, |newline| newline + 1);
+    let first = old_tokens.iter()
+        .position(|token| token.span.end.offset >= line_start)
+        .unwrap_or(old_tokens.len());
+    let restart = if first == 0 {
+        span::Position::default()
+    } else {
+        old_tokens[first - 1].span.end
+    };
+
+    let delta = edit.replacement.len() as isize - edit.range.len() as isize;
+    let edit_end = edit.range.start + edit.replacement.len();
+
+    // the text of the old tokens is in the old source, take it from the
+    // new; the positions an error holds move along with the token
+    let rebase = |old : &token::Token<'_>, shift : &dyn Fn(span::Position) -> span::Position| {
+        let span = span::Span::new(shift(old.span.start), shift(old.span.end));
+        token::Token {
+            span,
+            token_type : old.token_type,
+            text : &new_source[span.start.offset..span.end.offset],
+            payload : old.payload.clone().map(|payload| match payload {
+                token::Payload::Error(reason) => token::Payload::Error(reason.map_positions(shift)),
+                payload => payload,
+            }),
+        }
+    };
+
+    let mut tokens : Vec<token::Token<'src>> = old_tokens[..first].iter()
+        .map(|old| rebase(old, &|position| position))
+        .collect();
+    // the old token that is the next candidate to resynchronize with
+    let mut candidate = first;
+    let mut resynchronized = None;
+
+    for token in driver::Lexer::recovering_from(new_source, restart) {
+        // a recovering lexer does not fail
+        let token = token.unwrap();
+
+        if token.span.start.offset >= edit_end {
+            while candidate < old_tokens.len()
+                && (old_tokens[candidate].span.start.offset < edit.range.end
+                    || moved(old_tokens[candidate].span.start.offset, delta)
+                       < token.span.start.offset) {
+                candidate += 1;
+            }
+            if let Some(old) = old_tokens.get(candidate) {
+                if moved(old.span.start.offset, delta) == token.span.start.offset
+                    && old.span.len() == token.span.len()
+                    && old.token_type == token.token_type
+                    && old.payload == token.payload {
+                    resynchronized = Some((candidate, token));
+                    break
+                }
+            }
+        }
+
+        tokens.push(token);
+    }
+
+    let new_end = tokens.len();
+    let old_end = match resynchronized {
+        Some((candidate, token)) => {
+            let anchor = old_tokens[candidate].span.start;
+            let line_delta = token.span.start.line as isize - anchor.line as isize;
+            let column_delta = token.span.start.column as isize - anchor.column as isize;
+            // only what is on the line of the anchor moves sideways
+            let shift = |position : span::Position| span::Position {
+                offset : moved(position.offset, delta),
+                line : (position.line as isize + line_delta) as usize,
+                column : if position.line == anchor.line {
+                    (position.column as isize + column_delta) as usize
+                } else {
+                    position.column
+                },
+            };
+            tokens.extend(old_tokens[candidate..].iter().map(|old| rebase(old, &shift)));
+            candidate
+        },
+        None => old_tokens.len(),
+    };
+
+    Relexed { tokens, old_range : first..old_end, new_range : first..new_end }
+}
+
+fn moved(offset : usize, delta : isize) -> usize {
+    (offset as isize + delta) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexeme;
+    use crate::test_util;
+
+    fn lex(source : &str) -> Vec<token::Token<'_>> {
+        driver::Lexer::recovering(source).map(|token| token.unwrap()).collect()
+    }
+
+    /// Relexes after the edit and checks that the result is what lexing
+    /// from scratch gives.
+    fn check(source : &str, edit : &TextEdit) -> (Range<usize>, Range<usize>) {
+        let new_source = edit.apply(source);
+        let relexed = relex(&lex(source), edit, &new_source);
+        assert_eq!(relexed.tokens, lex(&new_source),
+                   "{:?} after {:?}", source, edit);
+        (relexed.old_range, relexed.new_range)
+    }
+
+    #[test]
+    fn apply() {
+        assert_eq!(TextEdit::new(2..3, "yz").apply("a x b"), "a yz b");
+        assert_eq!(TextEdit::new(0..0, "{-").apply("x"), "{-x");
+    }
+
+    #[test]
+    fn local_edit() {
+        let source = "module M where\nf x = g x\ng y = y\nh = f (g h)\n";
+        // rename `g` in `f x = g x` to `gg`
+        let edit = TextEdit::new(21..22, "gg");
+        assert_eq!(check(source, &edit), (3..7, 3..7));
+
+        // the tokens on the same line move, the rest keep their columns
+        let new_source = edit.apply(source);
+        let relexed = relex(&lex(source), &edit, &new_source);
+        assert_eq!(relexed.tokens[6].text, "gg");
+        assert_eq!(relexed.tokens[7].span.start, span::Position::new(24, 1, 9));
+        assert_eq!(relexed.tokens[8].span.start, span::Position::new(26, 2, 0));
+    }
+
+    #[test]
+    fn edits_that_change_lines() {
+        let source = "a = b\nc = d\n";
+        check(source, &TextEdit::new(1..1, "\n\n"));
+        check(source, &TextEdit::new(4..7, ""));
+        check(source, &TextEdit::new(0..0, "x y\n  "));
+        check(source, &TextEdit::new(11..12, ""));
+    }
+
+    #[test]
+    fn opening_and_closing_comments() {
+        let source = "a b {- c -} d e";
+        // opening a comment takes the rest of the file
+        let (old_range, _) = check("a b c d e", &TextEdit::new(2..2, "{- "));
+        assert_eq!(old_range, 0..5);
+        // closing it right away keeps the rest
+        assert_eq!(check("a b c d e", &TextEdit::new(2..2, "{--} ")), (0..1, 0..2));
+        // removing the end of a comment
+        check(source, &TextEdit::new(9..11, ""));
+        // closing an unterminated comment
+        check("a {- b c d", &TextEdit::new(7..7, "-}"));
+        // removing the beginning of a comment
+        check(source, &TextEdit::new(4..6, ""));
+        // turning the end of a comment into an operator
+        check(source, &TextEdit::new(10..11, "+"));
+    }
+
+    #[test]
+    fn lexemes_that_looked_into_the_edit() {
+        // the `'` was an error, as were the escape and the number after it
+        let source = "f '\\111 x";
+        let edit = TextEdit::new(7..7, "'");
+        check(source, &edit);
+        let new_source = edit.apply(source);
+        let relexed = relex(&lex(source), &edit, &new_source);
+        assert_eq!(relexed.tokens[1].token_type, token::TokenType::CharLiteral);
+        assert_eq!(relexed.tokens[1].text, "'\\111'");
+
+        check("c = ' x", &TextEdit::new(6..6, "'"));
+        check("s = \"a \\\n", &TextEdit::new(8..8, "\\\""));
+    }
+
+    #[test]
+    fn errors_that_move() {
+        // the unterminated comment is kept, but where it opens has moved
+        let source = "a b {- c";
+        let edit = TextEdit::new(0..1, "aaaa");
+        assert_eq!(check(source, &edit), (0..1, 0..1));
+        let new_source = edit.apply(source);
+        let relexed = relex(&lex(source), &edit, &new_source);
+        assert!(matches!(relexed.tokens[2].error(),
+                         Some(lexeme::Error::UnterminatedBlockComment { outermost, .. })
+                         if *outermost == span::Position::new(7, 0, 7)));
+
+        check("a b {- {- c", &TextEdit::new(0..1, "x\ny"));
+        check("a b\n {- {- c", &TextEdit::new(2..3, "bb"));
+    }
+
+    #[test]
+    fn edits_at_the_ends() {
+        check("", &TextEdit::new(0..0, "x"));
+        check("x", &TextEdit::new(0..1, ""));
+        check("x y", &TextEdit::new(3..3, "z"));
+        check("x y", &TextEdit::new(3..3, " z"));
+        check("x y", &TextEdit::new(0..0, "w"));
+        check("  x", &TextEdit::new(0..1, ""));
+    }
+
+    #[test]
+    fn random_edits() {
+        let source = "module M where\nf {- a -} x = x :+ y\n  where y = {- {- -} -} x\n\
+                      c = '\\111' : '\\n' : \"a\\SOH\\&b\" ++ \"\\  \\\"\n";
+        let pieces = ["", "x", "{-", "-}", " ", "\n", "::", ":", "y'", "where", "\"",
+                      "'", "\\", "111", "\\n", "\\^A", "'a'", "\\&", "SO", "H", "\\\""];
+        let mut random = test_util::Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let start = random.next() % (source.len() + 1);
+            let end = start + random.next() % (source.len() + 1 - start).min(6);
+            let replacement = pieces[random.next() % pieces.len()];
+            check(source, &TextEdit::new(start..end, replacement));
+        }
+    }
+}
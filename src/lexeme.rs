@@ -1,19 +1,148 @@
 use super::token;
 use super::regex;
 use super::dfa;
+use super::span;
+use super::string;
+use super::value;
 
 /// all recognizers implement this
 pub trait Lexeme {
 
-    fn recognize(input : &str) -> Result<token::Token, Error>;
+    fn recognize(input : &str) -> Result<token::Token<'_>, Error>;
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Error {
     Regex(regex::Error),
     Automata(dfa::Error),
     FoundConflictingLexeme,
     NotRecognized,
     FoundDistant,
-    NonAsciiFound,
+    /// The lexeme has started, but the input ended before it did.
+    UnexpectedEndOfInput,
+    /// A string was opened, but it is broken.
+    String(string::Error),
+    /// A literal is well-formed, but it has no value.
+    Literal(value::Error),
+    /// A block comment is not closed. `nested` holds where the `{-` inside
+    /// of it that are still open are, from the outside in, so `depth` is
+    /// one more than their number.
+    UnterminatedBlockComment {
+        outermost : span::Position,
+        nested : Vec<span::Position>,
+        depth : usize,
+    },
+    /// A `{-` is one more than `LexerLimits::max_comment_nesting` allows.
+    CommentTooDeep { limit : usize, at : span::Position },
+    /// A lexeme goes on past `LexerLimits::max_token_length`.
+    TokenTooLong { limit : usize },
+    /// There are more tokens than `LexerLimits::max_token_count`.
+    TooManyTokens { limit : usize },
+    /// The source code is larger than `LexerLimits::max_input_size`.
+    InputTooLarge { limit : usize },
+    /// The cancellation hook of the lexer asked it to stop.
+    Cancelled,
+    /// A `{-#` is not closed with a `#-}`.
+    UnterminatedPragma,
+}
+
+impl Error {
+    /// Tells if the recognizer simply did not find its lexeme here, as
+    /// opposed to finding the beginning of a lexeme that goes wrong later.
+    pub fn is_mismatch(&self) -> bool {
+        matches!(self,
+                 Error::Regex(regex::Error::NoMatch)
+                 | Error::Regex(regex::Error::DistantMatch)
+                 | Error::Automata(dfa::Error::IsInFailState)
+                 | Error::FoundConflictingLexeme
+                 | Error::NotRecognized
+                 | Error::FoundDistant)
+    }
+
+    /// A stable identifier of the error, it does not change between
+    /// releases, so that tools can refer to it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotRecognized => "L0001",
+            Error::UnexpectedEndOfInput => "L0003",
+            Error::FoundConflictingLexeme => "L0004",
+            Error::FoundDistant => "L0005",
+            Error::Regex(_) => "L0006",
+            Error::Automata(_) => "L0007",
+            Error::String(_) => "L0008",
+            Error::Literal(_) => "L0009",
+            Error::UnterminatedBlockComment { .. } => "L0010",
+            Error::CommentTooDeep { .. } => "L0011",
+            Error::TokenTooLong { .. } => "L0012",
+            Error::TooManyTokens { .. } => "L0013",
+            Error::InputTooLarge { .. } => "L0014",
+            Error::Cancelled => "L0015",
+            Error::UnterminatedPragma => "L0016",
+        }
+    }
+
+    /// Moves the positions the error holds, which are relative to where
+    /// the lexeme starts, to `base`, see `span::Span::shifted()`.
+    pub fn shifted(self, base : span::Position) -> Self {
+        self.map_positions(|position| position.shifted(base))
+    }
+
+    /// Applies `f` to the positions the error holds, as when the source
+    /// around them is edited.
+    pub fn map_positions(self, f : impl Fn(span::Position) -> span::Position) -> Self {
+        match self {
+            Error::UnterminatedBlockComment { outermost, nested, depth } =>
+                Error::UnterminatedBlockComment {
+                    outermost : f(outermost),
+                    nested : nested.into_iter().map(&f).collect(),
+                    depth,
+                },
+            Error::CommentTooDeep { limit, at } =>
+                Error::CommentTooDeep { limit, at : f(at) },
+            _ => self,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Regex(reason) => write!(f, "{}", reason),
+            Error::Automata(reason) => write!(f, "{}", reason),
+            Error::FoundConflictingLexeme =>
+                write!(f, "the lexeme is reserved and cannot be used here"),
+            Error::NotRecognized => write!(f, "unrecognized input"),
+            Error::FoundDistant => write!(f, "the lexeme does not start here"),
+            Error::UnexpectedEndOfInput => write!(f, "unexpected end of input"),
+            Error::String(reason) => write!(f, "{}", reason),
+            Error::Literal(reason) => write!(f, "{}", reason),
+            Error::UnterminatedBlockComment { depth : 1, .. } =>
+                write!(f, "unterminated block comment"),
+            Error::UnterminatedBlockComment { depth, .. } =>
+                write!(f, "unterminated block comment, {} nested comments in it are not closed either",
+                       depth - 1),
+            Error::CommentTooDeep { limit, .. } =>
+                write!(f, "comments are nested deeper than the limit of {}", limit),
+            Error::TokenTooLong { limit } =>
+                write!(f, "the token is longer than {} bytes", limit),
+            Error::TooManyTokens { limit } =>
+                write!(f, "there are more than {} tokens", limit),
+            Error::InputTooLarge { limit } =>
+                write!(f, "the input is larger than {} bytes", limit),
+            Error::Cancelled => write!(f, "lexing was cancelled"),
+            Error::UnterminatedPragma => write!(f, "unterminated pragma"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Regex(reason) => Some(reason),
+            Error::Automata(reason) => Some(reason),
+            Error::String(reason) => Some(reason),
+            Error::Literal(reason) => Some(reason),
+            _ => None,
+        }
+    }
 }
@@ -1,18 +1,68 @@
-#[macro_use]
-extern crate lazy_static;
-
 // public API is marked as pub
-mod mlcomment;
-mod ascii;
-mod dfa;
-mod token;
-mod lexeme;
-mod pda;
-mod driver;
-mod qident;
-mod reserved;
-mod regex;
-mod reused;
+pub mod mlcomment;
+pub mod comment;
+pub mod pragma;
+pub mod dfa;
+pub mod combined;
+pub mod tables;
+pub mod spec;
+mod spec_syntax;
+pub mod token;
+pub mod lexeme;
+pub mod pda;
+pub mod driver;
+pub mod limits;
+pub mod qident;
+pub mod literals;
+pub mod string;
+pub mod value;
+pub mod reserved;
+pub mod regex;
+pub mod reused;
+pub mod span;
+pub mod diagnostics;
+pub mod trivia;
+pub mod incremental;
+#[cfg(test)]
+mod test_util;
+
+pub use driver::{Lexer, LexError};
+pub use limits::LexerLimits;
+
+/// Derives `regex::RegexLexeme` out of a `#[lexeme(...)]` attribute, all
+/// the lexemes of `reused`, `reserved`, `qident`, `literals` and
+/// `comment` are defined this way. An expression may be put together out
+/// of other lexemes, those are checked by a test that the derive writes.
+///
+/// ```
+/// use proof_of_concept_haskell_lexer::RegexLexeme;
+/// use proof_of_concept_haskell_lexer::lexeme::Lexeme;
+///
+/// #[derive(RegexLexeme)]
+/// #[lexeme(regex = "[0-9]+", token = IntegerLiteral)]
+/// struct Decimal {}
+///
+/// assert_eq!(Decimal::recognize("42 x").unwrap().text, "42");
+///
+/// #[derive(RegexLexeme)]
+/// #[lexeme(regex = ["-?", Decimal], token = IntegerLiteral, except = "-0")]
+/// struct Signed {}
+///
+/// assert_eq!(Signed::recognize("-42 x").unwrap().text, "-42");
+/// assert!(Signed::recognize("-0").is_err());
+/// ```
+///
+/// An invalid expression does not compile.
+///
+/// ```compile_fail
+/// #[derive(proof_of_concept_haskell_lexer::RegexLexeme)]
+/// #[lexeme(regex = "(unclosed", token = QVarId)]
+/// struct Broken {}
+/// ```
+pub use proof_of_concept_haskell_lexer_derive::RegexLexeme;
+
+// the derived code names the crate, which works inside of it too this way
+extern crate self as proof_of_concept_haskell_lexer;
 
 // the application follows the following conventions
 // for dealing with errors
@@ -23,15 +73,15 @@ mod reused;
 // have escape sequences. Ex.: ```if r'\' == '\\' { true } else { false } ```
 // evaluates to true.
 
-// It works with ASCII by
+// It works with Unicode by
 // - reading the input from file into a string. In Rust, a string is UTF-8-encoded
 // sequence of chaaracters (char), 1 char takes 4 bytes, so it is represented by
 // u32 type.
-// - then collecting the bytes it contains. One byte takes 8 bits and is represented
-// by u8.
+// - then walking the chars it contains, while the offsets into the string are
+// counted in bytes, as one char takes from 1 to 4 bytes in UTF-8.
 //
-// Of course, in case the file was UTF-8 encoded and had unicode characters, the
-// bytes are not guaranteed to correspond to what we call letters.
+// The character classes of the report (uniSmall, uniLarge, uniSymbol, ...) are
+// defined by Unicode general categories in `reused`.
 
 
 // It is a convention to use raw string literals
@@ -0,0 +1,48 @@
+//! Bounds on the work a lexer does, for input that cannot be trusted, such
+//! as uploads. Every limit that is hit is reported with an error of its
+//! own, see `lexeme::Error`, instead of a panic or of lexing on and on.
+//!
+//! ```
+//! use proof_of_concept_haskell_lexer::{Lexer, LexerLimits};
+//! use proof_of_concept_haskell_lexer::lexeme;
+//!
+//! let limits = LexerLimits { max_token_count : 2, ..LexerLimits::default() };
+//! let errors : Vec<_> = Lexer::new("x = y").with_limits(limits)
+//!     .filter_map(Result::err)
+//!     .collect();
+//! assert_eq!(errors[0].reason, lexeme::Error::TooManyTokens { limit : 2 });
+//! ```
+
+/// The limits of a `driver::Lexer`, see `Lexer::with_limits()`. The
+/// default is no limit at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LexerLimits {
+    /// How many `{-` may be open at once, the outermost one counts.
+    pub max_comment_nesting : usize,
+    /// Length of a token in bytes. A lexeme that may still go on past it
+    /// is too long, even if it turns out shorter in the end, so that no
+    /// more than that is ever read for one token.
+    pub max_token_length : usize,
+    /// How many tokens one lexer makes, the error tokens and whitespace
+    /// of a lossless lexer count too.
+    pub max_token_count : usize,
+    /// Size of the source code in bytes.
+    pub max_input_size : usize,
+}
+
+impl LexerLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_comment_nesting : usize::MAX,
+            max_token_length : usize::MAX,
+            max_token_count : usize::MAX,
+            max_input_size : usize::MAX,
+        }
+    }
+}
+
+impl Default for LexerLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
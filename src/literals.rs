@@ -1,52 +1,198 @@
-/// asterisk (*) on the left side of a derivation marks terminals
-/// ->    == what is on the left becomes what is on the right
-/// ( x ) == apply to what is inside the parenthesis
-/// w/o{} == exclude what is inside the brackets
-/// |     == alternation
-/// [a-z] == any character covered by the range
-/// { x } == any numer of repetitions
-/// x ?   == zero or one repetition
-/// x +   == one or more repetition
+//! asterisk (*) on the left side of a derivation marks terminals
+//! ->    == what is on the left becomes what is on the right
+//! ( x ) == apply to what is inside the parenthesis
+//! w/o{} == exclude what is inside the brackets
+//! |     == alternation
+//! [a-z] == any character covered by the range
+//! { x } == any numer of repetitions
+//! x ?   == zero or one repetition
+//! x +   == one or more repetition
+//!
+//! ---- Global rules ----
+//! literal -> integer | float | char | string
+//!
+//! --- Integer literals ---
+//! integer -> decimal | 0o octal | 0O octal | 0x hexadecimal | 0X hexadecimal
+//!
+//! --- Float literals ---
+//! float -> decimal . decimal [ exponent ] | decimal exponent
+//! exponent -> ( e | E ) [ + | - ] decimal
+//!
+//! --- Character literals ---
+//! char -> ' ( graphic w/o{ ' | \ } | space | escape w/o{ \& } ) '
+//! graphic -> [a-z] | [A-Z] | symbol | digit | special | " | '
+//! escape -> \ ( charsec | ascii | decimal | o octal | x hexadecimal )
+//! *charsec* -> a | b | f | n | r | t | v | \ | " | ' | &
+//! *ascii* -> ^ cntrl | NUL | SOH | STX | ETX | EOT | ENQ | ACK
+//!        | BEL | BS | HT | LF | VT | FF | CR | SO | SI | DLE
+//!        | DC1 | DC2 | DC3 | DC4 | NAK | SYN | ETB | CAN
+//!        | EM | SUB | ESC | FS | GS | RS | US | SP | DEL
+//! cntrl -> A-Z | @ | [ | \ | ] | ^ | _
+//! decimal -> digit { digit }
+//! octal -> octit { octit }
+//! *octit* -> [0-7]
+//! hexadecimal -> hexit { hexit }
+//! *hexit* -> digit | A-F | a-f
+//! *symbol* -> ! | # | $ | % | & | ⋆ | + | . | / | < | = | > | ? | @
+//!           | \ | ^ | | | - | ~ | :
+//! *digit* -> [0-9]
+//! *special* -> ( | ) | , | ; | [ | ] | ` | { | }
+//!
+//! graphic
+//! A-Z | a-z | 0-9
+//!   ( | ) | , | ; | [ | ] | ` | { | } |  ! | # | $ | % | & | ⋆ | + | . | / | < |
+//!   = | > | ? | @ | \ | ^ | | | - | ~ | : | |
+//!
+//! escape
+//! \
+//!   a | b | f | n | r | t | v | \ | " | ' | &
+//!   [0-9]+ | o [0-7]+ | x [A-Fa-f0-9]+
+//!   | NUL | SOH | STX | ETX | EOT | ENQ | ACK
+//!   | BEL | BS | HT | LF | VT | FF | CR | SO | SI | DLE
+//!   | DC1 | DC2 | DC3 | DC4 | NAK | SYN | ETB | CAN
+//!   | EM | SUB | ESC | FS | GS | RS | US | SP | DEL
+//!        ^
+//!           [A-Z] | @ | [ | \ | ] | ^ | _
+//!
+//! gap
+//! \ ('\n' | '\r\n' | '\r' |  '\v' | '\f' | ' ' | '\t')+ \
+//! \ LF | CR LF | CR | VT | FF | SPACE | TAB
+
+use crate::regex;
+use crate::reused;
+use crate::string;
+
+use regex::RegexLexeme;
+use crate::RegexLexeme;
+
+use once_cell::sync::OnceCell;
+
+/// integer -> decimal | 0o octal | 0O octal | 0x hexadecimal | 0X hexadecimal
 ///
-/// ---- Global rules ----
-/// literal -> integer | float | char | string
+/// The prefixed ones go first, so that `0x1F` is not cut down to `0`.
+#[derive(RegexLexeme)]
+#[lexeme(regex = ["0[oO][", reused::Octit, "]+|0[xX][", reused::Hexit, "]+|[", reused::Digit, "]+"],
+         token = IntegerLiteral)]
+pub struct Integer {}
+
+/// float -> decimal . decimal [ exponent ] | decimal exponent
 ///
-/// --- Character literals ---
-/// char -> ' ( graphic w/o{ ' | \ } | space | escape w/o{ \& } ) '
-/// graphic -> [a-z] | [A-Z] | symbol | digit | special | " | '
-/// escape -> \ ( charsec | ascii | decimal | o octal | x hexadecimal )
-/// *charsec* -> a | b | f | n | r | t | v | \ | " | ' | &
-/// *ascii* -> ^ cntrl | NUL | SOH | STX | ETX | EOT | ENQ | ACK
-///        | BEL | BS | HT | LF | VT | FF | CR | SO | SI | DLE
-///        | DC1 | DC2 | DC3 | DC4 | NAK | SYN | ETB | CAN
-///        | EM | SUB | ESC | FS | GS | RS | US | SP | DEL
-/// cntrl -> A-Z | @ | [ | \ | ] | ^ | _
+/// Both sides of the dot need digits, so `1.e5` and `1..10` are not
+/// floats, and neither is the `e` of `1.0e`, which has none after it.
+#[derive(RegexLexeme)]
+#[lexeme(regex = [decimal(), r"\.", decimal(), "(", exponent(), ")?|", decimal(), exponent()],
+         token = FloatLiteral)]
+pub struct Float {}
+
 /// decimal -> digit { digit }
-/// octal -> octit { octit }
-/// *octit* -> [0-7]
-/// hexadecimal -> hexit { hexit }
-/// *hexit* -> digit | A-F | a-f
-/// *symbol* -> ! | # | $ | % | & | ⋆ | + | . | / | < | = | > | ? | @
-///           | \ | ^ | | | - | ~ | :
-/// *digit* -> [0-9]
-/// *special* -> ( | ) | , | ; | [ | ] | ` | { | }
-
-/// graphic
-/// A-Z | a-z | 0-9
-///   ( | ) | , | ; | [ | ] | ` | { | } |  ! | # | $ | % | & | ⋆ | + | . | / | < |
-///   = | > | ? | @ | \ | ^ | | | - | ~ | : | |
-
-/// escape
-/// \
-///   a | b | f | n | r | t | v | \ | " | ' | &
-///   [0-9]+ | o [0-7]+ | x [A-Fa-f0-9]+
-///   | NUL | SOH | STX | ETX | EOT | ENQ | ACK
-///   | BEL | BS | HT | LF | VT | FF | CR | SO | SI | DLE
-///   | DC1 | DC2 | DC3 | DC4 | NAK | SYN | ETB | CAN
-///   | EM | SUB | ESC | FS | GS | RS | US | SP | DEL
-///        ^
-///           [A-Z] | @ | [ | \ | ] | ^ | _
+fn decimal() -> String {
+    format!("[{}]+", reused::Digit::expression())
+}
+
+/// exponent -> ( e | E ) [ + | - ] decimal
+fn exponent() -> String {
+    format!("[eE][+-]?{}", decimal())
+}
+
+/// escape w/o{ \& }, the empty escape is only allowed in strings
+fn escape() -> &'static str {
+    static EXPRESSION : OnceCell<String> = OnceCell::new();
+    EXPRESSION.get_or_init(|| {
+        format!(r#"\\([abfnrtv\\"']|\^[A-Z@\[\\\]^_]|{}|[{}]+|o[{}]+|x[{}]+)"#,
+                string::ASCII.join("|"),
+                reused::Digit::expression(),
+                reused::Octit::expression(),
+                reused::Hexit::expression())
+    })
+}
+
+/// graphic -> small | large | symbol | digit | special | " | '
+/// but without the quote, which ends a char literal anyway
+pub(crate) fn graphic() -> &'static str {
+    static EXPRESSION : OnceCell<String> = OnceCell::new();
+    EXPRESSION.get_or_init(|| {
+        format!(r#"{}{}{}{}{}(),;\[\]`{{}}""#,
+                reused::Small::expression(),
+                reused::Large::expression(),
+                reused::Symbol::expression(),
+                reused::Digit::expression(),
+                reused::UniDigit::expression())
+    })
+}
+
+/// char -> ' ( graphic w/o{ ' | \ } | space | escape w/o{ \& } ) '
 ///
-/// gap
-/// \ ('\n' | '\r\n' | '\r' |  '\v' | '\f' | ' ' | '\t')+ \
-/// \ LF | CR LF | CR | VT | FF | SPACE | TAB
+/// A quote after an identifier belongs to the identifier, maximal munch
+/// makes `x'` a `QVarId`, and `f 'x'` a `QVarId` and a char.
+#[derive(RegexLexeme)]
+#[lexeme(regex = [r"'([[", graphic(), r" ]--[\\]]|", escape(), ")'"], token = CharLiteral)]
+pub struct Char {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexeme::Lexeme;
+
+    fn integer(input : &str) -> Option<&str> {
+        Integer::recognize(input).ok().map(|token| token.text)
+    }
+
+    #[test]
+    fn integer_literals() {
+        assert_eq!(integer("42 x"), Some("42"));
+        assert_eq!(integer("007"), Some("007"));
+        assert_eq!(integer("0x1F"), Some("0x1F"));
+        assert_eq!(integer("0XfF+"), Some("0XfF"));
+        assert_eq!(integer("0o17"), Some("0o17"));
+        assert_eq!(integer("0O78"), Some("0O7"));
+        // without digits, the prefix is not a part of the literal
+        assert_eq!(integer("0x"), Some("0"));
+        assert_eq!(integer("0o8"), Some("0"));
+        assert_eq!(integer("x1"), None);
+        // only ASCII digits
+        assert_eq!(integer("\u{663}"), None);
+    }
+
+    fn float(input : &str) -> Option<&str> {
+        Float::recognize(input).ok().map(|token| token.text)
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(float("3.14"), Some("3.14"));
+        assert_eq!(float("1e10"), Some("1e10"));
+        assert_eq!(float("1.5E-3"), Some("1.5E-3"));
+        assert_eq!(float("2e+7"), Some("2e+7"));
+        assert_eq!(float("1.0e"), Some("1.0"));
+        assert_eq!(float("1.0e+"), Some("1.0"));
+        assert_eq!(float("1.e5"), None);
+        assert_eq!(float("1..10"), None);
+        assert_eq!(float("42"), None);
+        assert_eq!(float(".5"), None);
+    }
+
+    fn char(input : &str) -> Option<&str> {
+        Char::recognize(input).ok().map(|token| token.text)
+    }
+
+    #[test]
+    fn char_literals() {
+        for literal in &["'a'", "'Z'", "' '", "'\"'", "'\u{3bb}'", "'\u{2192}'", "'_'",
+                         "'('", "'`'", "'9'"] {
+            assert_eq!(char(literal), Some(*literal));
+        }
+        for escape in &["a", "b", "f", "n", "r", "t", "v", "\\", "\"", "'",
+                        "^A", "^@", "^[", "^\\", "^]", "^^", "^_",
+                        "NUL", "SOH", "SO", "DEL", "SP", "DC1",
+                        "0", "1114111", "o17", "x7F", "xaB"] {
+            let literal = format!("'\\{}'", escape);
+            assert_eq!(char(&literal), Some(literal.as_str()));
+        }
+        assert_eq!(char("'a' x"), Some("'a'"));
+        // not chars
+        for input in &["''", "'''", "'\\'", "'\\&'", "'ab'", "'a", "'\t'", "'\n'",
+                       "'\\^a'", "'\\q'", "'\\o8'", "'\\xg'", "'\\SOHX'", "x'"] {
+            assert_eq!(char(input), None, "{}", input);
+        }
+    }
+}
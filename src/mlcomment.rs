@@ -1,31 +1,135 @@
 use crate::dfa;
+use crate::limits;
 use crate::pda;
-use crate::ascii;
 use crate::lexeme;
 use crate::token;
-
-use dfa::DFA;
+use crate::span;
 
 /// Multiline comment DFA.
-
 pub struct MLComment {
     state : MLCommentState,
-    stack : u8,
+    // where the `{-` that are not closed yet are, from the outside in
+    stack : Vec<span::Position>,
+    // where the next input is, relative to the beginning of the comment
+    position : span::Position,
+    // where the last `{` is, it opens a comment if a `-` follows
+    bracket : span::Position,
+    max_nesting : usize,
+    // the `{-` at `bracket` was one too many
+    too_deep : bool,
 }
 
 impl MLComment {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             state : MLCommentState::Initial,
-            stack : 0,
+            stack : Vec::new(),
+            position : span::Position::default(),
+            bracket : span::Position::default(),
+            max_nesting : usize::MAX,
+            too_deep : false,
+        }
+    }
+
+    fn open(&mut self) {
+        if self.stack.len() == self.max_nesting {
+            self.state = MLCommentState::FailedMatch;
+            self.too_deep = true;
+        } else {
+            self.stack.push(self.bracket);
+        }
+    }
+
+    fn close(&mut self) {
+        self.stack.pop();
+    }
+
+    fn step(&mut self, at : span::Position, input : char) {
+        match (&mut self.state, &input) {
+
+            // Final/Failed
+            (MLCommentState::Final, _) => {},
+            (MLCommentState::FailedMatch, _) => {},
+
+            // Initial -> *
+            (MLCommentState::Initial, '{') => {
+                self.state = MLCommentState::SawOpeningBracket;
+                self.bracket = at;
+            },
+            (MLCommentState::Initial, _) => {
+                self.state = MLCommentState::FailedMatch;
+            },
+
+            // SawOpeningBracket -> *
+            (MLCommentState::SawOpeningBracket, '-') => {
+                self.state = MLCommentState::Opened;
+                self.open();
+            },
+            (MLCommentState::SawOpeningBracket, _) => {
+                self.state = MLCommentState::FailedMatch;
+            },
+
+            // Opened -> *
+            (MLCommentState::Opened, '#') => {
+                self.state = MLCommentState::FailedMatch;
+                self.stack.clear();
+            },
+            (MLCommentState::Opened, _) => {
+                self.state = MLCommentState::SawOpenComm;
+                self.step(at, input);
+            },
+
+            // SawOpenComm -> *
+            (MLCommentState::SawOpenComm, '-') => {
+                self.state = MLCommentState::SawDashAfterOpenComm;
+            },
+            (MLCommentState::SawOpenComm, '{') => {
+                self.state = MLCommentState::SawOpeningBracketInner;
+                self.bracket = at;
+            },
+            (MLCommentState::SawOpenComm, _) => {},
+
+            // SawDashAfterOpenComm -> *
+            (MLCommentState::SawDashAfterOpenComm, '}') => {
+                self.close();
+                if self.stack.is_empty() {
+                    self.state = MLCommentState::Final;
+                } else {
+                    self.state = MLCommentState::SawOpenComm;
+                }
+            },
+            // as in `--}`, the last dash may still close it
+            (MLCommentState::SawDashAfterOpenComm, '-') => {},
+            (MLCommentState::SawDashAfterOpenComm, _) => {
+                self.state = MLCommentState::SawOpenComm;
+                self.step(at, input);
+            },
+
+            // SawOpeningBracketInner -> *
+            (MLCommentState::SawOpeningBracketInner, '-') => {
+                self.state = MLCommentState::SawOpenComm;
+                self.open();
+            },
+            (MLCommentState::SawOpeningBracketInner, _) => {
+                self.state = MLCommentState::SawOpenComm;
+                self.step(at, input);
+            },
         }
     }
 }
 
+impl Default for MLComment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(PartialEq)]
 pub enum MLCommentState {
     Initial,
     SawOpeningBracket,
+    /// Right after the outermost `{-`, where a `#` makes it a pragma.
+    Opened,
     SawOpenComm,
     SawDashAfterOpenComm,
     //SawClosingBracket,
@@ -35,13 +139,13 @@ pub enum MLCommentState {
 }
 
 impl pda::PDA for MLComment {
-    type Stack = u8;
+    type Stack = Vec<span::Position>;
 }
 
 impl dfa::DFA for MLComment {
 
-    /// works with ASCII chars
-    type Input = ascii::ASCIIChar;
+    /// works with any Unicode chars, only the brackets and dashes matter
+    type Input = char;
 
     /// and has a custom enum for describing the states
     type State = MLCommentState;
@@ -54,117 +158,45 @@ impl dfa::DFA for MLComment {
         self.state == Self::State::FailedMatch
     }
 
-    // this implicitly implements the transition table as all DFAs do
-    // and uses a stack for detecting correct bracket sequences
-    fn advance(&mut self, input : Self::Input) {
-        let input = input.get_char();
-        match (&mut self.state, &input) {
-
-            // Final/Failed
-            (Self::State::Final, char) => {
-                ()
-            }
-            (Self::State::FailedMatch, char) => {
-                ()
-            },
-
-            // Initial -> *
-            (Self::State::Initial, '{') => {
-                self.state = Self::State::SawOpeningBracket;
-            },
-            (Self::State::Initial, char) => {
-                self.state = Self::State::FailedMatch;
-            },
-
-            // SawOpeningBracket -> *
-            (Self::State::SawOpeningBracket, '-') => {
-                self.state = Self::State::SawOpenComm;
-                self.stack += 1;
-            },
-            (Self::State::SawOpeningBracket, char) => {
-                self.state = Self::State::FailedMatch;
-            },
+    /// inside a comment that is not closed yet
+    fn is_incomplete(&self) -> bool {
+        !self.stack.is_empty()
+    }
 
-            // SawOpenComm -> *
-            (Self::State::SawOpenComm, '-') => {
-                self.state = Self::State::SawDashAfterOpenComm;
-            },
-            (Self::State::SawOpenComm, '{') => {
-                self.state = Self::State::SawOpeningBracketInner;
-            },
-            (Self::State::SawOpenComm, char) => {},
+    /// where the comments that are not closed yet are opened, relative to
+    /// the beginning of the outermost one
+    fn error(&self) -> Option<lexeme::Error> {
+        if self.too_deep {
+            return Some(lexeme::Error::CommentTooDeep {
+                limit : self.max_nesting,
+                at : self.bracket,
+            })
+        }
+        let (outermost, nested) = self.stack.split_first()?;
+        Some(lexeme::Error::UnterminatedBlockComment {
+            outermost : *outermost,
+            nested : nested.to_vec(),
+            depth : self.stack.len(),
+        })
+    }
 
-            // SawDashAfterOpenComm -> *
-            (Self::State::SawDashAfterOpenComm, '}') => {
-                self.stack -= 1;
-                if self.stack == 0 {
-                    self.state = Self::State::Final;
-                } else {
-                    self.state = Self::State::SawOpenComm;
-                }
-            },
-            (Self::State::SawDashAfterOpenComm, char) => {
-                self.state = Self::State::SawOpenComm;
-            },
+    fn set_limits(&mut self, limits : &limits::LexerLimits) {
+        self.max_nesting = limits.max_comment_nesting;
+    }
 
-            // SawOpeningBracketInner -> *
-            (Self::State::SawOpeningBracketInner, '-') => {
-                self.stack += 1;
-                self.state = Self::State::SawOpenComm;
-            },
-            (Self::State::SawOpeningBracketInner, char) => {
-                self.state = Self::State::SawOpenComm;
-            },
-        }
+    // this implicitly implements the transition table as all DFAs do
+    // and uses a stack for detecting correct bracket sequences
+    fn advance(&mut self, input : Self::Input) {
+        let at = self.position;
+        self.position = at.advance_char(input);
+        self.step(at, input);
     }
 }
 
 impl lexeme::Lexeme for MLComment {
 
-    fn recognize(input : &str) -> Result<token::Token, lexeme::Error> {
-        let mut rec = MLComment::new();
-        let mut cols_in_curr_line = 0;
-        let mut lines_span : Vec<u16> = vec![];
-
-        for character in input.chars() {
-            match ascii::ASCIIChar::new(character) {
-                Some(ascii_char) => {
-                    match ascii_char.get_char() {
-                        '\n' => {
-                            lines_span.push(cols_in_curr_line);
-                            cols_in_curr_line = 0;
-                        },
-                        _ => {
-                            cols_in_curr_line += 1;
-                        },
-                    };
-
-                    // if it is in a final state but there is still more
-                    // to see? should not happen when the stack is 0, so what
-                    // is after another opening bracket is another token
-
-                    rec.advance(ascii_char);
-                    if rec.in_final_state() {
-
-                        // do not forget the last line
-                        lines_span.push(cols_in_curr_line);
-
-                        return Ok(token::Token
-                                    { token_type :
-                                      token::TokenType::MLComment,
-                                      span : lines_span,
-                                    })
-                    } else if rec.in_fail_state() {
-                        return Err(lexeme::Error::Automata(dfa::Error::IsInFailState))
-                    }
-                },
-                None => {
-                    return Err(lexeme::Error::NonAsciiFound)
-                }
-            };
-        }
-
-        Err(lexeme::Error::NotRecognized)
+    fn recognize(input : &str) -> Result<token::Token<'_>, lexeme::Error> {
+        dfa::recognize_with(MLComment::new(), token::TokenType::MLComment, input)
     }
 }
 
@@ -172,6 +204,7 @@ impl lexeme::Lexeme for MLComment {
 mod test {
     use super::*;
     use super::lexeme::Lexeme;
+    use super::dfa::DFA;
 
     #[test]
     fn lexeme() {
@@ -180,7 +213,9 @@ mod test {
                        token::Token
                        { token_type :
                          token::TokenType::MLComment,
-                         span : vec![4],
+                         payload : None,
+                         text : "{--}",
+                         span : span::Span::of("{--}"),
                        }));
 
         assert_eq!(MLComment::recognize("{-dfasdfasdf-}"),
@@ -188,7 +223,9 @@ mod test {
                        token::Token
                        { token_type :
                          token::TokenType::MLComment,
-                         span : vec![14],
+                         payload : None,
+                         text : "{-dfasdfasdf-}",
+                         span : span::Span::of("{-dfasdfasdf-}"),
                        }));
 
         assert_eq!(MLComment::recognize("{-{--}-}"),
@@ -196,7 +233,9 @@ mod test {
                        token::Token
                        { token_type :
                          token::TokenType::MLComment,
-                         span : vec![8],
+                         payload : None,
+                         text : "{-{--}-}",
+                         span : span::Span::of("{-{--}-}"),
                        }));
 
 
@@ -205,8 +244,75 @@ mod test {
                        token::Token
                        { token_type :
                          token::TokenType::MLComment,
-                         span : vec![2, 6],
+                         payload : None,
+                         text : "{-\n{--}-}",
+                         span : span::Span::new(
+                             span::Position::new(0, 0, 0),
+                             span::Position::new(9, 1, 6)),
                        }));
 
+        // the comment ends where it is closed
+        assert_eq!(MLComment::recognize("{--} x").unwrap().text, "{--}");
+
+        // columns count characters, offsets count bytes
+        assert_eq!(MLComment::recognize("{- \u{3bb} \u{2192} -}").unwrap().span,
+                   span::Span::new(span::Position::new(0, 0, 0),
+                                   span::Position::new(12, 0, 9)));
+
+        // dashes run on, and brackets too, before the ones that count
+        assert_eq!(MLComment::recognize("{- x --} y").unwrap().text, "{- x --}");
+        assert_eq!(MLComment::recognize("{- {{- -} -} y").unwrap().text, "{- {{- -} -}");
+        assert_eq!(MLComment::recognize("{"),
+                   Err(lexeme::Error::NotRecognized));
+
+        // a pragma is not a comment, but it may be inside of one
+        assert_eq!(MLComment::recognize("{-# INLINE f #-}"),
+                   Err(lexeme::Error::Automata(dfa::Error::IsInFailState)));
+        assert_eq!(MLComment::recognize("{- {-# INLINE f #-} -}").unwrap().text,
+                   "{- {-# INLINE f #-} -}");
+    }
+
+    #[test]
+    fn unterminated() {
+        assert_eq!(MLComment::recognize("{- x"),
+                   Err(lexeme::Error::UnterminatedBlockComment {
+                       outermost : span::Position::default(),
+                       nested : vec![],
+                       depth : 1,
+                   }));
+
+        // the closed ones are left out, wherever they are
+        assert_eq!(MLComment::recognize("{- {- -}\n  {- \u{3bb} {-\n {--} -}"),
+                   Err(lexeme::Error::UnterminatedBlockComment {
+                       outermost : span::Position::default(),
+                       nested : vec![span::Position::new(11, 1, 2)],
+                       depth : 2,
+                   }));
+
+        let deep = "{-".repeat(5);
+        match MLComment::recognize(&deep) {
+            Err(lexeme::Error::UnterminatedBlockComment { nested, depth, .. }) => {
+                assert_eq!(depth, 5);
+                assert_eq!(nested.last(), Some(&span::Position::new(8, 0, 8)));
+            },
+            other => panic!("{:?} is not unterminated", other),
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        // deeper than a byte can count
+        let deep = format!("{}{}", "{-".repeat(300), "-}".repeat(300));
+        assert_eq!(MLComment::recognize(&deep).unwrap().text, deep);
+
+        let mut rec = MLComment::new();
+        rec.set_limits(&limits::LexerLimits { max_comment_nesting : 2,
+                                              ..limits::LexerLimits::default() });
+        "{- {- -} {- \n {-".chars().for_each(|character| rec.advance(character));
+        assert!(rec.in_fail_state());
+        assert_eq!(rec.error(), Some(lexeme::Error::CommentTooDeep {
+            limit : 2,
+            at : span::Position::new(14, 1, 1),
+        }));
     }
 }
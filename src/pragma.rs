@@ -0,0 +1,265 @@
+//! Pragmas, which look like block comments but are not, as in
+//! `{-# LANGUAGE GADTs #-}`. They are read by a hand-written automaton up
+//! to the first `#-}`, they do not nest. The name of a pragma is matched
+//! regardless of case, the way GHC does, and what follows it is split into
+//! arguments; a pragma of another name is kept as it is written.
+
+use crate::dfa;
+use crate::lexeme;
+use crate::token;
+
+/// Pragma DFA.
+pub struct Pragma {
+    state : PragmaState,
+}
+
+impl Pragma {
+    pub fn new() -> Self {
+        Self { state : PragmaState::Initial }
+    }
+}
+
+impl Default for Pragma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PragmaState {
+    Initial,
+    SawOpeningBracket,
+    SawDash,
+    Inside,
+    SawHash,
+    SawHashDash,
+    Final,
+    FailedMatch,
+}
+
+impl dfa::DFA for Pragma {
+
+    type Input = char;
+
+    type State = PragmaState;
+
+    fn in_final_state(&self) -> bool {
+        self.state == Self::State::Final
+    }
+
+    fn in_fail_state(&self) -> bool {
+        self.state == Self::State::FailedMatch
+    }
+
+    /// after the `{-#`, but before the `#-}`
+    fn is_incomplete(&self) -> bool {
+        matches!(self.state,
+                 Self::State::Inside | Self::State::SawHash | Self::State::SawHashDash)
+    }
+
+    fn error(&self) -> Option<lexeme::Error> {
+        if self.is_incomplete() {
+            Some(lexeme::Error::UnterminatedPragma)
+        } else {
+            None
+        }
+    }
+
+    fn advance(&mut self, input : Self::Input) {
+        self.state = match (self.state, input) {
+
+            // Final/Failed
+            (Self::State::Final, _) => Self::State::Final,
+            (Self::State::FailedMatch, _) => Self::State::FailedMatch,
+
+            // the opening `{-#`
+            (Self::State::Initial, '{') => Self::State::SawOpeningBracket,
+            (Self::State::SawOpeningBracket, '-') => Self::State::SawDash,
+            (Self::State::SawDash, '#') => Self::State::Inside,
+            (Self::State::Initial, _)
+                | (Self::State::SawOpeningBracket, _)
+                | (Self::State::SawDash, _) => Self::State::FailedMatch,
+
+            // the closing `#-}`, `##-}` closes it as well
+            (Self::State::Inside, '#') => Self::State::SawHash,
+            (Self::State::Inside, _) => Self::State::Inside,
+            (Self::State::SawHash, '-') => Self::State::SawHashDash,
+            (Self::State::SawHash, '#') => Self::State::SawHash,
+            (Self::State::SawHash, _) => Self::State::Inside,
+            (Self::State::SawHashDash, '}') => Self::State::Final,
+            (Self::State::SawHashDash, '#') => Self::State::SawHash,
+            (Self::State::SawHashDash, _) => Self::State::Inside,
+        }
+    }
+}
+
+impl lexeme::Lexeme for Pragma {
+
+    fn recognize(input : &str) -> Result<token::Token<'_>, lexeme::Error> {
+        dfa::recognize_with(Pragma::new(), token::TokenType::Pragma, input)
+    }
+}
+
+/// The pragmas GHC knows of.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Name {
+    Language,
+    OptionsGhc,
+    OptionsHaddock,
+    Include,
+    Inline,
+    NoInline,
+    Inlinable,
+    Specialize,
+    Rules,
+    Unpack,
+    NoUnpack,
+    Source,
+    Deprecated,
+    Warning,
+    Minimal,
+    Overlapping,
+    Overlappable,
+    Overlaps,
+    Incoherent,
+    Complete,
+    Ann,
+    Line,
+    Column,
+    Scc,
+}
+
+impl Name {
+    /// The name of a pragma, in any case, as in `{-# language GADTs #-}`.
+    /// The British spellings and `INLINEABLE` are the same pragmas.
+    pub fn of(name : &str) -> Option<Self> {
+        use Name::*;
+        Some(match name.to_ascii_uppercase().as_str() {
+            "LANGUAGE" => Language,
+            "OPTIONS_GHC" => OptionsGhc,
+            "OPTIONS_HADDOCK" => OptionsHaddock,
+            "INCLUDE" => Include,
+            "INLINE" => Inline,
+            "NOINLINE" | "NOTINLINE" => NoInline,
+            "INLINABLE" | "INLINEABLE" => Inlinable,
+            "SPECIALIZE" | "SPECIALISE" => Specialize,
+            "RULES" => Rules,
+            "UNPACK" => Unpack,
+            "NOUNPACK" => NoUnpack,
+            "SOURCE" => Source,
+            "DEPRECATED" => Deprecated,
+            "WARNING" => Warning,
+            "MINIMAL" => Minimal,
+            "OVERLAPPING" => Overlapping,
+            "OVERLAPPABLE" => Overlappable,
+            "OVERLAPS" => Overlaps,
+            "INCOHERENT" => Incoherent,
+            "COMPLETE" => Complete,
+            "ANN" => Ann,
+            "LINE" => Line,
+            "COLUMN" => Column,
+            "SCC" => Scc,
+            _ => return None,
+        })
+    }
+
+    /// How the arguments are written: extensions are separated by commas,
+    /// the options and most of the rest by whitespace, and the pragmas
+    /// that hold Haskell code, like rules and signatures, are taken whole.
+    fn arguments(self, text : &str) -> Vec<String> {
+        use Name::*;
+        match self {
+            Language => text.split(',')
+                .map(str::trim)
+                .filter(|argument| !argument.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Specialize | Rules | Deprecated | Warning | Minimal | Complete | Ann => {
+                let text = text.trim();
+                if text.is_empty() { vec![] } else { vec![text.to_string()] }
+            },
+            _ => text.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// What a pragma says.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Contents {
+    Known { name : Name, arguments : Vec<String> },
+    /// A pragma of another name, the text between `{-#` and `#-}`,
+    /// without the whitespace around it.
+    Unknown(String),
+}
+
+/// Reads the text of a token of `token_type`. It is `None` for the tokens
+/// that are not pragmas.
+pub fn contents(token_type : token::TokenType, text : &str) -> Option<Contents> {
+    if token_type != token::TokenType::Pragma {
+        return None
+    }
+    let inside = text.strip_prefix("{-#")?.strip_suffix("#-}")?.trim();
+    let (name, rest) = inside.split_at(inside.find(char::is_whitespace)
+                                       .unwrap_or(inside.len()));
+    Some(match Name::of(name) {
+        Some(name) => Contents::Known { name, arguments : name.arguments(rest) },
+        None => Contents::Unknown(inside.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::lexeme::Lexeme;
+
+    fn known(name : Name, arguments : &[&str]) -> Option<Contents> {
+        Some(Contents::Known {
+            name,
+            arguments : arguments.iter().map(|argument| argument.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn lexeme() {
+        fn pragma(input : &str) -> Result<&str, lexeme::Error> {
+            Pragma::recognize(input).map(|token| token.text)
+        }
+        assert_eq!(pragma("{-# LANGUAGE GADTs #-} x"), Ok("{-# LANGUAGE GADTs #-}"));
+        assert_eq!(pragma("{-##-}"), Ok("{-##-}"));
+        assert_eq!(pragma("{-# X ##-}"), Ok("{-# X ##-}"));
+        // pragmas do not nest, the first `#-}` closes it
+        assert_eq!(pragma("{-# A {-# B #-} #-}"), Ok("{-# A {-# B #-}"));
+        assert_eq!(pragma("{-# INLINE\n  f #-}"), Ok("{-# INLINE\n  f #-}"));
+        assert_eq!(pragma("{-# INLINE f -}"), Err(lexeme::Error::UnterminatedPragma));
+        assert_eq!(pragma("{- x -}"), Err(lexeme::Error::Automata(dfa::Error::IsInFailState)));
+        assert_eq!(pragma("{-"), Err(lexeme::Error::NotRecognized));
+    }
+
+    #[test]
+    fn names() {
+        use token::TokenType::Pragma;
+        assert_eq!(contents(Pragma, "{-# LANGUAGE GADTs, RankNTypes #-}"),
+                   known(Name::Language, &["GADTs", "RankNTypes"]));
+        assert_eq!(contents(Pragma, "{-# language GADTs #-}"),
+                   known(Name::Language, &["GADTs"]));
+        assert_eq!(contents(Pragma, "{-#Options_GHC -Wall -O2#-}"),
+                   known(Name::OptionsGhc, &["-Wall", "-O2"]));
+        assert_eq!(contents(Pragma, "{-# INLINE [2] f #-}"),
+                   known(Name::Inline, &["[2]", "f"]));
+        assert_eq!(contents(Pragma, "{-# SPECIALISE f :: Int -> Int #-}"),
+                   known(Name::Specialize, &["f :: Int -> Int"]));
+        assert_eq!(contents(Pragma, "{-# RULES\n  \"map/map\" forall f g xs. map f (map g xs) = map (f . g) xs\n  #-}"),
+                   known(Name::Rules,
+                         &["\"map/map\" forall f g xs. map f (map g xs) = map (f . g) xs"]));
+        assert_eq!(contents(Pragma, "{-# UNPACK #-}"), known(Name::Unpack, &[]));
+    }
+
+    #[test]
+    fn unknown() {
+        use token::TokenType::Pragma;
+        assert_eq!(contents(Pragma, "{-# FOO bar  baz #-}"),
+                   Some(Contents::Unknown("FOO bar  baz".to_string())));
+        assert_eq!(contents(Pragma, "{-# #-}"), Some(Contents::Unknown(String::new())));
+        assert_eq!(contents(token::TokenType::MLComment, "{- LANGUAGE GADTs -}"), None);
+    }
+}
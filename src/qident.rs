@@ -1,181 +1,188 @@
-/// asterisk (*) on the left side of a derivation marks terminals
-/// ->    == what is on the left becomes what is on the right
-/// ( x ) == apply to what is inside the parenthesis
-/// w/o{} == exclude what is inside the brackets
-/// |     == alternation
-/// [a-z] == any character covered by the range
-/// { x } == any numer of repetitions
-/// x ?   == zero or one repetition
-/// x +   == one or more repetition
-///
-/// ---- Global rules ----
-/// qconid -> [modid .] conid
-/// qvarid -> [modid .] varid
-/// qvarsym -> [modid .] varsym
-///
-/// conid -> LARGE {SMALL | LARGE | DIGIT | '}
-/// varid -> ( SMALL {SMALL | LARGE | DIGIT | '} ) / reservedid
-/// varsym -> ( ( symbol ) / ':' { symbol } ) / ( reservedop | dashes )
-/// modid -> {conid .} conid
-
-use crate::token;
-use crate::lexeme;
+//! asterisk (*) on the left side of a derivation marks terminals
+//! ->    == what is on the left becomes what is on the right
+//! ( x ) == apply to what is inside the parenthesis
+//! w/o{} == exclude what is inside the brackets
+//! |     == alternation
+//! [a-z] == any character covered by the range
+//! { x } == any numer of repetitions
+//! x ?   == zero or one repetition
+//! x +   == one or more repetition
+//!
+//! ---- Global rules ----
+//! qconid -> [modid .] conid
+//! qvarid -> [modid .] varid
+//! qvarsym -> [modid .] varsym
+//!
+//! conid -> LARGE {SMALL | LARGE | DIGIT | '}
+//! varid -> ( SMALL {SMALL | LARGE | DIGIT | '} ) / reservedid
+//! varsym -> ( ( symbol ) / ':' { symbol } ) / ( reservedop | dashes )
+//! modid -> {conid .} conid
+
+use crate::comment;
 use crate::regex;
+use crate::reserved;
+use crate::reused;
+
+use regex::RegexLexeme;
+use crate::RegexLexeme;
+
+/// SMALL | LARGE | DIGIT | ', the characters that may follow the first
+/// one in an identifier
+fn idchar() -> String {
+    format!("[{}{}{}{}']",
+            reused::Small::expression(),
+            reused::Large::expression(),
+            reused::Digit::expression(),
+            reused::UniDigit::expression())
+}
 
-use std::convert::TryInto;
-use crate::regex::RegexLexeme;
+/// {conid .}
+fn qualifier() -> String {
+    format!(r"([{}]{}*\.)*", reused::Large::expression(), idchar())
+}
 
+#[derive(RegexLexeme)]
+#[lexeme(regex = [qualifier(), "[", reused::Large, "]", idchar(), "*"], token = QConId)]
 pub struct QConId {}
 
-impl regex::RegexLexeme for QConId {
-
-    fn expression() -> &'static str {
-        r"(([A-Z][A-Za-z0-9']*\.)*[A-Z][A-Za-z0-9']*\.)?[A-Z][A-Za-z0-9']*"
-    }
-    fn token_type() -> token::TokenType {
-        token::TokenType::QConId
-    }
-}
-
+/// varid w/o{ reservedid }, a qualifier does not make a reservedid a
+/// varid, `M.where` is not a qvarid
+#[derive(RegexLexeme)]
+#[lexeme(regex = [qualifier(), "[", reused::Small, "]", idchar(), "*"], token = QVarId,
+         except = [qualifier(), "(", reserved::ReservedId, ")"])]
 pub struct QVarId {}
 
-/// DONE
-impl regex::RegexLexeme for QVarId {
-
-    fn needs_filtering() -> bool { true }
-
-    fn except_for() -> &'static str {
-        /*
-        vec!["as", "case", "class", "data",
-             "default", "deriving", "do", "else",
-             "foreign", "if", "import", "in",
-             "infix", "infixl", "infixr", "instance",
-             "let", "module", "newtype", "of",
-             "qualified", "then", "type", "where"]
-         */
-        r""
-    }
-
-    fn expression() -> &'static str {
-        r"(([A-Z][A-Za-z0-9']*\.)*[A-Z][A-Za-z0-9']*\.)?[a-z][A-Za-z0-9']*"
-    }
-
-    fn token_type() -> token::TokenType {
-        token::TokenType::QVarId
-    }
-}
-
+/// varsym w/o{ reservedop | dashes }, dashes start a comment, even
+/// after a qualifier; the first symbol is not ':', that is what makes it
+/// a varsym
+#[derive(RegexLexeme)]
+#[lexeme(regex = [qualifier(), "[[", reused::Symbol, "]&&[^:]][", reused::Symbol, "]*"],
+         token = QVarSym,
+         except = [qualifier(), "(", reserved::ReservedOp, "|", comment::dashes(), ")"])]
 pub struct QVarSym {}
 
-impl regex::RegexLexeme for QVarSym {
-
-    fn needs_filtering() -> bool { true }
-
-    fn except_for() -> &'static str {
-        /*
-        vec![
-            r"--", // this is a dirty trick, I need a regex for representing dashes
-            r"..", r":", r"::", r"=", r"\", r"|", r"<-", r"->", r"@", r"~", r"=>"
-        ]
-         */
-        r""
-    }
-
-    fn expression() -> &'static str {
-        r"(([A-Z][A-Za-z0-9']*\.)*[A-Z][A-Za-z0-9']*\.)?([!|#|$|%|&|*|+|\.|/|<|=|>|?|@|\|^|\||-|~])?([!|#|$|%|&|*|+|\.|/|<|=|>|?|@|\|^|\||-|~|:])*"
-    }
-
-    fn token_type() -> token::TokenType {
-        token::TokenType::QVarSym
-    }
-}
-
+/// consym w/o{ reservedop }, it starts with ':', so it is never
+/// dashes, and `:--` is an operator
+#[derive(RegexLexeme)]
+#[lexeme(regex = [qualifier(), ":[", reused::Symbol, "]*"], token = QConSym,
+         except = [qualifier(), "(", reserved::ReservedOp, ")"])]
 pub struct QConSym {}
 
-impl regex::RegexLexeme for QConSym {
-
-    fn needs_filtering() -> bool { true }
-
-    fn except_for() -> &'static str {
-        /*
-        vec![
-            r"--", // this is a dirty trick, I need a regex for representing dashes
-            r"..", r":", r"::", r"=", r"\", r"|", r"<-", r"->", r"@", r"~", r"=>"
-        ]
-         */
-        r""
-    }
-
-    fn expression() -> &'static str {
-        r"(([A-Z][A-Za-z0-9']*\.)*[A-Z][A-Za-z0-9']*\.)?:([!|#|$|%|&|*|+|\.|/|<|=| > |?|@|\|^|\||-|~|:])*"
-    }
-
-    fn token_type() -> token::TokenType {
-        token::TokenType::QConSym
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::lexeme;
     use crate::lexeme::Lexeme;
 
     #[test]
     fn qconid() {
         // todo remove unwraps, use Ok() and Err()
         let res = QConId::recognize("A.F").unwrap();
-        assert_eq!(res.span, vec![3]);
+        assert_eq!(res.span.len(), 3);
         let res = QConId::recognize("A.F.f").unwrap();
-        assert_eq!(res.span, vec![3]); // 3, not 5!
+        assert_eq!(res.span.len(), 3); // 3, not 5!
         let res = QConId::recognize(".");
         assert_eq!(res, Err (lexeme::Error::Regex
                              (regex::Error::NoMatch)));
         let res = QConId::recognize("A'.F'.f").unwrap();
-        assert_eq!(res.span, vec![5]);
+        assert_eq!(res.span.len(), 5);
         let res = QConId::recognize("Aa2'.F2f'.f22").unwrap();
-        assert_eq!(res.span, vec![9]);
+        assert_eq!(res.span.len(), 9);
 
         // examples from the report (2.4 Identifiers and Operators)
         let res = QConId::recognize("f.g");
         assert_eq!(res, Err (lexeme::Error::Regex
                              (regex::Error::NoMatch)));
         let res = QConId::recognize("F.g").unwrap();
-        assert_eq!(res.span, vec![1]); // F, g is small, so the
+        assert_eq!(res.span.len(), 1); // F, g is small, so the
         // expression is not qconid!
         let res = QConId::recognize("f..");
         assert_eq!(res, Err (lexeme::Error::Regex
                              (regex::Error::NoMatch)));
         let res = QConId::recognize("F..").unwrap();
-        assert_eq!(res.span, vec![1]); // qualified, but not qconid!
+        assert_eq!(res.span.len(), 1); // qualified, but not qconid!
         // the same thind as with F.g, it is not qconid, but it would be
         // if g was G, like here
         let res = QConId::recognize("F.G").unwrap();
-        assert_eq!(res.span, vec![3]); // perfectly valid qconid
+        assert_eq!(res.span.len(), 3); // perfectly valid qconid
         let res = QConId::recognize("F.").unwrap();
-        assert_eq!(res.span, vec![1]); // It started as qconid, but no
+        assert_eq!(res.span.len(), 1); // It started as qconid, but no
     }
 
     #[test]
     fn qvarid() {
-        let res = QVarId::recognize("f.g");
+        let res = QVarId::recognize("f.g").unwrap();
+        assert_eq!(res.span.len(), 1);
 	// assert_eq!(res, Err (lexeme::Error::Regex(regex::Error::NoMatch)));
         //let res = QVarId::recognize("F.g").unwrap();
-        //assert_eq!(res.span, vec![0]); // F, g is small, so the
+        //assert_eq!(res.span.len(), 0); // F, g is small, so the
         // expression is not qconid!
         //let res = QVarId::recognize("f..");
         //assert_eq!(res, Err (lexeme::Error::Regex(regex::Error::NoMatch)));
         //let res = QVarId::recognize("F..").unwrap();
-        //assert_eq!(res.span, vec![1]); // qualified, but not qconid!
+        //assert_eq!(res.span.len(), 1); // qualified, but not qconid!
         // the same thind as with F.g, it is not qconid, but it would be
         // if g was G, like here
         //let res = QVarId::recognize("F.G").unwrap();
-        //assert_eq!(res.span, vec![3]); // perfectly valid qconid
+        //assert_eq!(res.span.len(), 3); // perfectly valid qconid
         //let res = QVarId::recognize("F.").unwrap();
-        //assert_eq!(res.span, vec![1]); // It started as qconid, but no
+        //assert_eq!(res.span.len(), 1); // It started as qconid, but no
+    }
+
+    #[test]
+    fn qvarid_excludes_reservedid() {
+        assert_eq!(QVarId::recognize("import"),
+                   Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarId::recognize("imports").unwrap().span.len(), 7);
+        assert_eq!(QVarId::recognize("_x").unwrap().span.len(), 2);
+        // a qualified reserved word is no qvarid either
+        assert_eq!(QVarId::recognize("M.where"), Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarId::recognize("M.N.do"), Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarId::recognize("M.wheres").unwrap().span.len(), 8);
     }
 
     #[test]
     fn qvarsym() {
+        assert_eq!(QVarSym::recognize(">>= f").unwrap().span.len(), 3);
+        assert_eq!(QVarSym::recognize(r"\\").unwrap().span.len(), 2);
+        assert_eq!(QVarSym::recognize("M.+ x").unwrap().span.len(), 3);
+        assert_eq!(QVarSym::recognize("->"),
+                   Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarSym::recognize("M.->"),
+                   Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarSym::recognize(":+"), Err(lexeme::Error::Regex(
+            regex::Error::NoMatch)));
+    }
+
+    #[test]
+    fn qvarsym_excludes_dashes() {
+        assert_eq!(QVarSym::recognize("--"), Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarSym::recognize("--- x"), Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarSym::recognize("M.--"), Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QVarSym::recognize("-->").unwrap().span.len(), 3);
+        assert_eq!(QVarSym::recognize("|--").unwrap().span.len(), 3);
+        assert_eq!(QVarSym::recognize("-").unwrap().span.len(), 1);
+        assert_eq!(QConSym::recognize(":--").unwrap().span.len(), 3);
+    }
 
+    #[test]
+    fn unicode() {
+        assert_eq!(QConId::recognize("\u{3a3}\u{3bb}\u{663}'").unwrap().span.len(), 7);
+        assert_eq!(QVarId::recognize("\u{3bb}x \u{3bb}").unwrap().span.len(), 3);
+        assert_eq!(QVarId::recognize("M\u{e9}.\u{e9}t\u{e9}").unwrap().span.len(), 9);
+        assert_eq!(QVarSym::recognize("\u{2192} x").unwrap().span.len(), 3);
+        assert_eq!(QConSym::recognize(":\u{2237}").unwrap().span.len(), 4);
+        // special characters are no symbols, even the Unicode ones
+        assert_eq!(QVarSym::recognize("+(").unwrap().span.len(), 1);
+    }
+
+    #[test]
+    fn qconsym() {
+        assert_eq!(QConSym::recognize(":+ x").unwrap().span.len(), 2);
+        assert_eq!(QConSym::recognize(":::").unwrap().span.len(), 3);
+        assert_eq!(QConSym::recognize("::"),
+                   Err(lexeme::Error::FoundConflictingLexeme));
+        assert_eq!(QConSym::recognize("M.::"),
+                   Err(lexeme::Error::FoundConflictingLexeme));
     }
 }
@@ -1,22 +1,42 @@
+use super::combined;
 use super::lexeme;
 use super::token;
+use super::span;
 
 use std::collections::HashMap;
 use std::sync::Mutex;
 use std::any::TypeId;
-use once_cell::sync::OnceCell;
+// the derived lexemes cache their expressions with it too
+#[doc(hidden)]
+pub use once_cell::sync::OnceCell;
 
 use regex as regex_backend;
-use std::convert::TryInto;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Error {
     NoMatch,
     DistantMatch,
-    InvalidExpression,
     LexemeNotAllowed,
+    /// An expression of the lexeme does not compile, which is a bug in
+    /// its definition, with why, see `RegexLexeme::check()`.
+    InvalidExpression(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::NoMatch => write!(f, "the expression does not match"),
+            Error::DistantMatch =>
+                write!(f, "the expression matches further in the input"),
+            Error::LexemeNotAllowed => write!(f, "the lexeme is not allowed here"),
+            Error::InvalidExpression(reason) =>
+                write!(f, "the expression of the lexeme is invalid: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 pub trait RegexLexeme {
 
     /// Activates the usage of except_for() filtering, false by
@@ -24,7 +44,8 @@ pub trait RegexLexeme {
     fn needs_filtering() -> bool { false }
 
     /// A regular expression that defines the matches that must be
-    /// avoided. NOOP by default.
+    /// avoided. A match is avoided only when this expression covers
+    /// it as a whole. NOOP by default.
     fn except_for() -> &'static str { "" }
 
     fn token_type() -> token::TokenType;
@@ -34,23 +55,92 @@ pub trait RegexLexeme {
     /// expression with [], instead implement CharGroup for your type.
     fn expression() -> &'static str;
 
+    /// The compiled expressions of the lexeme. They are compiled on the
+    /// first call only, the same way `CharacterGroup::expression` caches
+    /// its strings per type, and so is the error when they do not compile.
+    fn matcher() -> Result<&'static Matcher, Error>
+        where Self : Sized + 'static
+    {
+        type Compiled = Result<Matcher, Error>;
+        static VALUE : OnceCell<
+                Mutex<HashMap<TypeId, &'static Compiled>>>
+            = OnceCell::new();
+
+        let cache = VALUE.get_or_init(|| Mutex::new(HashMap::new()));
+        let cached = cache.lock().unwrap().get(&TypeId::of::<Self>()).copied();
+        let compiled = match cached {
+            Some(compiled) => compiled,
+            None => {
+                // compiling takes a while, so not under the lock
+                let compiled = Matcher::compile::<Self>()
+                    .map_err(|error| Error::InvalidExpression(error.to_string()));
+                *cache.lock().unwrap()
+                    .entry(TypeId::of::<Self>())
+                    .or_insert_with(|| Box::leak(Box::new(compiled)))
+            },
+        };
+        compiled.as_ref().map_err(Clone::clone)
+    }
+
+    /// Compiles the expressions of the lexeme, for `recognize()` and for a
+    /// `CombinedAutomaton` alike, and tells if they are invalid. It is
+    /// meant to run once, when a lexer is set up, `recognize()` does not
+    /// report it: an invalid expression matches nothing. The derived
+    /// lexemes run it in a test of their own.
+    fn check() -> Result<(), Error>
+        where Self : Sized + 'static
+    {
+        Self::matcher()?;
+        combined::CombinedAutomaton::new(vec![combined::Definition::of::<Self>()])
+            .map_err(|error| Error::InvalidExpression(error.to_string()))?;
+        Ok(())
+    }
+
     /// For internal use, this function deals with regex implementation
-    /// details.
+    /// details. The expression is anchored at the beginning of `input`,
+    /// so that a lexeme that is not there does not make the regex look
+    /// through the rest of the file. An invalid expression matches
+    /// nothing, see `check()`.
     fn recognize_raw_match(input : &str) ->
-        Result<regex_backend::Match, Error> {
-        match regex_backend::Regex::new(Self::expression()) {
-            Err(e) => Err(Error::InvalidExpression),
-            Ok(matcher) => {
-                match matcher.find(input) {
-                    Some(position) => {
-                        if (position.start() > 0)
-                        { Err(Error::DistantMatch) }
-                        else { Ok(position) }
-                    },
-                    None => { Err(Error::NoMatch) }
-                }
-            }
-        }
+        Result<regex_backend::Match<'_>, Error>
+        where Self : Sized + 'static
+    {
+        Self::matcher().map_err(|_| Error::NoMatch)?
+            .expression.find(input).ok_or(Error::NoMatch)
+    }
+
+    /// For internal use, tells if the whole match is covered by
+    /// except_for().
+    fn is_excluded(matched : &str) -> bool
+        where Self : Sized + 'static
+    {
+        Self::matcher().ok()
+            .and_then(|matcher| matcher.except_for.as_ref())
+            .is_some_and(|except_for| except_for.is_match(matched))
+    }
+}
+
+/// The expressions of a `RegexLexeme`, compiled and anchored.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    /// `expression()`, anchored at the beginning of the input.
+    pub expression : regex_backend::Regex,
+    /// `except_for()`, anchored at both ends, when the lexeme needs
+    /// filtering.
+    pub except_for : Option<regex_backend::Regex>,
+}
+
+impl Matcher {
+    /// Compiles the expressions of `T`.
+    pub fn compile<T : RegexLexeme>() -> Result<Self, regex_backend::Error> {
+        let expression = regex_backend::Regex::new(
+            &format!("^(?:{})", T::expression()))?;
+        let except_for = if T::needs_filtering() {
+            Some(regex_backend::Regex::new(&format!("^(?:{})$", T::except_for()))?)
+        } else {
+            None
+        };
+        Ok(Self { expression, except_for })
     }
 }
 
@@ -62,17 +152,17 @@ pub trait CharacterGroup : RegexLexeme
     /// Calculates the internal string that is returned by `format!`
     /// only once, returns a view on it that has static lifetime.
     fn expression() -> &'static str {
-        static value : OnceCell<
+        static VALUE : OnceCell<
                 Mutex<HashMap<TypeId, &'static str>>>
             = OnceCell::new();
 
         let _: &'static Mutex<HashMap<TypeId, &'static str>> =
-            value.get_or_init(|| {
+            VALUE.get_or_init(|| {
                 Mutex::new(HashMap::new())
             });
 
         let v: &'static str =
-            value
+            VALUE
             .get()
             .unwrap()
             .lock()
@@ -90,42 +180,41 @@ pub trait CharacterGroup : RegexLexeme
 
 impl <T : RegexLexeme + 'static> CharacterGroup for T {}
 
-/// TODO Find a way to implement Maybe monad. The general pattern is
-/// `try to compute; if not possible, wrap the internal error in this
-/// more general enum, and return the result`. Nested ifs become
-/// clumsy.
+// TODO Find a way to implement Maybe monad. The general pattern is
+// `try to compute; if not possible, wrap the internal error in this
+// more general enum, and return the result`. Nested ifs become
+// clumsy.
 
 impl<T> lexeme::Lexeme for T
-where T : RegexLexeme
+where T : RegexLexeme + 'static
 {
 
     /// Wraps the general Lexeme trait for all RegexLexemes;
     /// - serves the purpose of error handling
     /// - optionally performs filtering with RegexLexeme::except_for()
-    fn recognize(input : &str) -> Result<token::Token, lexeme::Error> {
+    fn recognize(input : &str) -> Result<token::Token<'_>, lexeme::Error> {
 
         if <Self as RegexLexeme>::needs_filtering() {
-            let except_for = <Self as RegexLexeme>::except_for();
-            if let Ok(position) = <Self as RegexLexeme>::recognize_raw_match(input) {
-                // todo this needs to be changed
-                if except_for.contains(&position.as_str()) {
-                    Err(lexeme::Error::FoundConflictingLexeme)
-                } else {
-                    Ok(token::Token { span : vec![ (position.end() - position.start())
-                                                   .try_into().unwrap() ],
-                                      token_type : Self::token_type(),
-                    })
+            match <Self as RegexLexeme>::recognize_raw_match(input) {
+                Ok(position) => {
+                    if <Self as RegexLexeme>::is_excluded(position.as_str()) {
+                        Err(lexeme::Error::FoundConflictingLexeme)
+                    } else {
+                        Ok(token::Token::new(Self::token_type(),
+                                             span::Span::of(position.as_str()),
+                                             position.as_str()))
+                    }
+                },
+                Err(reason) => {
+                    Err(lexeme::Error::Regex(reason))
                 }
-            } else {
-                Err(lexeme::Error::Regex(Error::NoMatch))
             }
         } else {
             match <Self as RegexLexeme>::recognize_raw_match(input) {
                 Ok(position) => {
-                    Ok(token::Token { span : vec![(position.end() - position.start())
-                                                  .try_into().unwrap()],
-                                      token_type : Self::token_type(),
-                    })
+                    Ok(token::Token::new(Self::token_type(),
+                                        span::Span::of(position.as_str()),
+                                        position.as_str()))
                 },
                 Err(reason) => {
                     Err(lexeme::Error::Regex(reason))
@@ -134,3 +223,56 @@ where T : RegexLexeme
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Broken;
+
+    impl RegexLexeme for Broken {
+        fn token_type() -> token::TokenType { token::TokenType::Error }
+        fn expression() -> &'static str { "(unclosed" }
+    }
+
+    #[test]
+    fn compiled_once() {
+        use crate::reserved::ReservedOp;
+        assert!(std::ptr::eq(ReservedOp::matcher().unwrap(), ReservedOp::matcher().unwrap()));
+        assert!(ReservedOp::matcher().unwrap().except_for.is_none());
+        assert!(crate::qident::QVarId::matcher().unwrap().except_for.is_some());
+    }
+
+    #[test]
+    fn anchored() {
+        use crate::qident::QVarId;
+        assert_eq!(QVarId::recognize_raw_match("x y").unwrap().as_str(), "x");
+        assert_eq!(QVarId::recognize_raw_match(" y").unwrap_err(), Error::NoMatch);
+        assert!(QVarId::is_excluded("where"));
+        assert!(!QVarId::is_excluded("wherever"));
+    }
+
+    #[test]
+    fn invalid_expression() {
+        assert!(Matcher::compile::<Broken>().is_err());
+        // reported when checked, without compiling again
+        assert!(matches!(Broken::check(), Err(Error::InvalidExpression(_))));
+        assert_eq!(Broken::matcher().unwrap_err(), Broken::matcher().unwrap_err());
+        // but not when used, it matches nothing
+        assert_eq!(<Broken as lexeme::Lexeme>::recognize("(unclosed"),
+                   Err(lexeme::Error::Regex(Error::NoMatch)));
+        assert_eq!(crate::reserved::ReservedOp::check(), Ok(()));
+    }
+
+    #[test]
+    fn valid_for_regex_only() {
+        // the regex crate takes Unicode word boundaries, a DFA does not
+        struct Word;
+        impl RegexLexeme for Word {
+            fn token_type() -> token::TokenType { token::TokenType::QVarId }
+            fn expression() -> &'static str { r"x\b" }
+        }
+        assert!(Word::matcher().is_ok());
+        assert!(matches!(Word::check(), Err(Error::InvalidExpression(_))));
+    }
+}
@@ -1,32 +1,22 @@
 use crate::*;
 
+// the word boundary makes the whole reserved word match, so that `infixl`
+// is not cut down to `in`; an ASCII one is enough, as a longer identifier
+// wins over the reserved word anyway, and the combined automaton can only
+// have an ASCII one
+#[derive(RegexLexeme)]
+#[lexeme(regex = r"(as|case|class|data|default|deriving|do|else|foreign|if|import|in|infix|infixl|infixr|instance|let|module|newtype|of|qualified|then|type|where|_)(?-u:\b)",
+         token = ReservedId)]
 pub struct ReservedId {}
 
-impl regex::RegexLexeme for ReservedId {
-    fn expression() -> &'static str {
-        r"(as|case|class|data|default|deriving|do|else|foreign|if|import|in|infix|infixl|infixr|instance|let|module|newtype|of|qualified|then|type|where|_)"
-    }
-
-    fn token_type() -> token::TokenType {
-        token::TokenType::ReservedId
-    }
-}
-
+// the last part is tricky! \\ is for \, and \| is for |, so the last
+// symbol in this string is not a delimiter!
+// update: important to escape the dots!
+// update: longer alternatives go first, the leftmost one wins
+#[derive(RegexLexeme)]
+#[lexeme(regex = r"\.\.|::|=>|<-|->|:|=|@|~|\\|\|", token = ReservedOp)]
 pub struct ReservedOp {}
 
-impl regex::RegexLexeme for ReservedOp {
-    fn expression() -> &'static str {
-        // the last part is tricky! \\ is for \, and \| is for |, so the last
-        // symbol in this string is not a delimiter!
-        // update: important to escape the dots!
-        r"\.\.|:|::|=|<-|->|@|~|=>|\\|\|"
-    }
-
-    fn token_type() -> token::TokenType {
-        token::TokenType::ReservedOp
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -35,7 +25,7 @@ mod test {
     #[test]
     fn lexeme_reserved_id() {
         let res = ReservedId::recognize("case").unwrap();
-        assert_eq!(res.span, [4]);
+        assert_eq!(res.span.len(), 4);
     }
 
     #[test]
@@ -43,7 +33,9 @@ mod test {
         let res = ReservedOp::recognize(r"\");//.unwrap();
         assert_eq!(res,
                    Ok(token::Token
-                      { span : vec![1],
-                        token_type : token::TokenType::ReservedOp}))
+                      { span : span::Span::of(r"\"),
+                        token_type : token::TokenType::ReservedOp,
+                        text : r"\",
+                        payload : None }))
     }
 }
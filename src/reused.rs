@@ -1,45 +1,98 @@
-/// This file contiains terminals used for recognizing lexemes in a
-/// Haskell program. They may be implemented with simple automata or
-/// regular expressions that do the same thing under the hood. Nothing
-/// more than regex is needed.
+//! This file contiains terminals used for recognizing lexemes in a
+//! Haskell program. They may be implemented with simple automata or
+//! regular expressions that do the same thing under the hood. Nothing
+//! more than regex is needed.
 
 use super::regex;
-use super::token;
+use super::RegexLexeme;
 
-struct BigASCII {}
-impl regex::RegexLexeme for BigASCII {
-    fn expression() -> &'static str { "A-Z" }
-    fn token_type() -> token::TokenType { token::TokenType::BigASCII }
-}
+#[derive(RegexLexeme)]
+#[lexeme(group = "A-Z", token = BigASCII)]
+pub struct BigASCII {}
 
-struct LowASCII {}
-impl regex::RegexLexeme for LowASCII {
-    fn expression() -> &'static str { "a-z" }
-    fn token_type() -> token::TokenType { token::TokenType::LowASCII }
-}
+#[derive(RegexLexeme)]
+#[lexeme(group = "a-z", token = LowASCII)]
+pub struct LowASCII {}
 
-struct Digit {}
-impl regex::RegexLexeme for Digit {
-    fn expression() -> &'static str { "0-9" }
-    fn token_type() -> token::TokenType { token::TokenType::Digit }
-}
+#[derive(RegexLexeme)]
+#[lexeme(group = "0-9", token = Digit)]
+pub struct Digit {}
 
-struct Octit {}
-impl regex::RegexLexeme for Octit {
-    fn expression() -> &'static str { "0-7" }
-    fn token_type() -> token::TokenType { token::TokenType::Octit }
-}
+#[derive(RegexLexeme)]
+#[lexeme(group = "0-7", token = Octit)]
+pub struct Octit {}
+
+#[derive(RegexLexeme)]
+#[lexeme(group = ["A-Fa-f", Digit], token = Hexit)]
+pub struct Hexit {}
+
+// ---- Unicode classes of the report, by general category ----
+
+/// uniSmall -> any Unicode lowercase letter
+#[derive(RegexLexeme)]
+#[lexeme(group = r"\p{Ll}", token = UniSmall)]
+pub struct UniSmall {}
+
+/// uniLarge -> any uppercase or titlecase Unicode letter
+#[derive(RegexLexeme)]
+#[lexeme(group = r"\p{Lu}\p{Lt}", token = UniLarge)]
+pub struct UniLarge {}
+
+/// uniDigit -> any Unicode decimal digit
+#[derive(RegexLexeme)]
+#[lexeme(group = r"\p{Nd}", token = UniDigit)]
+pub struct UniDigit {}
+
+/// uniSymbol -> any Unicode symbol or punctuation
+#[derive(RegexLexeme)]
+#[lexeme(group = r"\p{S}\p{P}", token = UniSymbol)]
+pub struct UniSymbol {}
+
+/// uniWhite -> any Unicode character defined as whitespace
+#[derive(RegexLexeme)]
+#[lexeme(group = r"\p{Zs}", token = UniWhite)]
+pub struct UniWhite {}
 
-struct Hexit {}
-impl regex::RegexLexeme for Hexit {
-    fn expression() -> &'static str {
-        static LAZY: ::lazy_static::lazy::Lazy<String> =
-            ::lazy_static::lazy::Lazy::INIT;
-        &*LAZY.get(|| { format!("A-Fa-f{}",
-                                <Digit as regex::RegexLexeme>::expression()) })
+// ---- Classes of the report that mix ASCII and Unicode ----
+
+/// small -> ascSmall | uniSmall | _
+#[derive(RegexLexeme)]
+#[lexeme(group = [LowASCII, UniSmall, "_"], token = Small)]
+pub struct Small {}
+
+/// large -> ascLarge | uniLarge
+#[derive(RegexLexeme)]
+#[lexeme(group = [BigASCII, UniLarge], token = Large)]
+pub struct Large {}
+
+/// symbol -> ascSymbol | uniSymbol w/o{ special | _ | " | ' }
+// inside of a character group, only \, ^, -, [ and ] need escaping; the
+// nested group is a set operation
+#[derive(RegexLexeme)]
+#[lexeme(group = [r"!#$%&*+./<=>?@\\\^|\-~:[[", UniSymbol, r#"]&&[^(),;\[\]`{}_"']]"#],
+         token = Symbol)]
+pub struct Symbol {}
+
+/// whitechar -> newline | vertab | space | tab | uniWhite
+#[derive(RegexLexeme)]
+#[lexeme(group = [r"\n\r\x0B\x0C \t", UniWhite], token = WhiteChar)]
+pub struct WhiteChar {}
+
+/// Tells if `character` is a whitechar, which is the most frequent
+/// question the driver asks, so ASCII does not go through the regex.
+pub fn is_whitechar(character : char) -> bool {
+    ::lazy_static::lazy_static! {
+        static ref WHITECHAR : ::regex::Regex = ::regex::Regex::new(
+            &format!("^{}$", <WhiteChar as regex::CharacterGroup>::expression()))
+            .unwrap();
     }
 
-    fn token_type() -> token::TokenType { token::TokenType::Hexit }
+    if character.is_ascii() {
+        matches!(character, '\n' | '\r' | '\x0B' | '\x0C' | ' ' | '\t')
+    } else {
+        let mut buffer = [0; 4];
+        WHITECHAR.is_match(character.encode_utf8(&mut buffer))
+    }
 }
 
 #[cfg(test)]
@@ -54,4 +107,28 @@ mod test {
         assert_eq!(<Hexit as regex::RegexLexeme>::expression(), "A-Fa-f0-9");
         assert_eq!(<Hexit as regex::CharacterGroup>::expression(), "[A-Fa-f0-9]");
     }
+
+    fn matches<T : regex::CharacterGroup>(input : &str) -> bool {
+        ::regex::Regex::new(&format!("^{}+$", <T as regex::CharacterGroup>::expression()))
+            .unwrap().is_match(input)
+    }
+
+    #[test]
+    fn unicode_classes() {
+        assert!(matches::<Small>("abc_\u{3bb}\u{df}"));
+        assert!(!matches::<Small>("A"));
+        assert!(matches::<Large>("AZ\u{3a3}\u{1c5}"));
+        assert!(!matches::<Large>("a"));
+        assert!(matches::<UniDigit>("09\u{663}"));
+        assert!(matches::<Symbol>("!#$%&*+./<=>?@\\^|-~:\u{2192}\u{2237}\u{b7}"));
+        for special in &["(", ")", ",", ";", "[", "]", "`", "{", "}", "_", "\"", "'"] {
+            assert!(!matches::<Symbol>(special), "{}", special);
+        }
+        assert!(is_whitechar(' '));
+        assert!(is_whitechar('\x0C'));
+        assert!(is_whitechar('\u{a0}'));
+        assert!(is_whitechar('\u{3000}'));
+        assert!(!is_whitechar('\u{200b}'));
+        assert!(!is_whitechar('x'));
+    }
 }
@@ -0,0 +1,185 @@
+/// A place in the source code. `offset` counts bytes from the beginning
+/// of file, `line` and `column` count from zero, and `column` counts
+/// characters, not bytes, so that it matches what an editor shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub offset : usize,
+    pub line : usize,
+    pub column : usize,
+}
+
+impl Position {
+    pub fn new(offset : usize, line : usize, column : usize) -> Self {
+        Self { offset, line, column }
+    }
+
+    /// The position right after `text`, given that `text` starts here.
+    pub fn advance(self, text : &str) -> Self {
+        let mut next = self;
+        for character in text.chars() {
+            next = next.advance_char(character);
+        }
+        next
+    }
+
+    /// The position right after `character`, given that it starts here.
+    pub fn advance_char(self, character : char) -> Self {
+        match character {
+            '\n' => Self { offset : self.offset + 1,
+                           line : self.line + 1,
+                           column : 0 },
+            _ => Self { offset : self.offset + character.len_utf8(),
+                        line : self.line,
+                        column : self.column + 1 },
+        }
+    }
+
+    /// Moves a position that was computed relative to `base` to where it
+    /// really is.
+    pub fn shifted(self, base : Position) -> Self {
+        Self {
+            offset : base.offset + self.offset,
+            line : base.line + self.line,
+            // only the first line continues the line of `base`
+            column : if self.line == 0 {
+                base.column + self.column
+            } else {
+                self.column
+            },
+        }
+    }
+}
+
+/// A half-open range of the source code, `end` points right after the
+/// last character. Spans are ordered by where they start, then by where
+/// they end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start : Position,
+    pub end : Position,
+}
+
+impl Span {
+    pub fn new(start : Position, end : Position) -> Self {
+        Self { start, end }
+    }
+
+    /// The span of `text`, as if it was at the very beginning of file.
+    pub fn of(text : &str) -> Self {
+        Self { start : Position::default(),
+               end : Position::default().advance(text) }
+    }
+
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tells if the byte at `offset` is covered by this span.
+    pub fn contains(&self, offset : usize) -> bool {
+        self.start.offset <= offset && offset < self.end.offset
+    }
+
+    /// The smallest span that covers both spans, and everything in
+    /// between.
+    pub fn merge(self, other : Self) -> Self {
+        Self { start : std::cmp::min(self.start, other.start),
+               end : std::cmp::max(self.end, other.end) }
+    }
+
+    /// Splits the span of `text` into the spans of its lines, the line
+    /// breaks are left out, and so is the empty line after a final one.
+    pub fn lines(self, text : &str) -> Vec<Self> {
+        let mut lines = vec![];
+        let mut start = self.start;
+        let mut position = self.start;
+        for character in text.chars() {
+            if character == '\n' {
+                let end = if text[..position.offset - self.start.offset].ends_with('\r') {
+                    Position { offset : position.offset - 1, column : position.column - 1,
+                               ..position }
+                } else {
+                    position
+                };
+                lines.push(Self::new(start, end));
+                position = position.advance_char(character);
+                start = position;
+            } else {
+                position = position.advance_char(character);
+            }
+        }
+        if start != position || lines.is_empty() {
+            lines.push(Self::new(start, position));
+        }
+        lines
+    }
+
+    /// Moves a span that was computed relative to `base` (as `Span::of`
+    /// does) to where it really is.
+    pub fn shifted(self, base : Position) -> Self {
+        Self { start : self.start.shifted(base), end : self.end.shifted(base) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn of() {
+        assert_eq!(Span::of("{-\n{--}-}"),
+                   Span::new(Position::new(0, 0, 0), Position::new(9, 1, 6)));
+        // columns count characters, offsets count bytes
+        assert_eq!(Span::of("\u{3bb}x"),
+                   Span::new(Position::new(0, 0, 0), Position::new(3, 0, 2)));
+    }
+
+    #[test]
+    fn contains() {
+        let span = Span::new(Position::new(2, 0, 2), Position::new(4, 0, 4));
+        assert!(!span.contains(1));
+        assert!(span.contains(2));
+        assert!(span.contains(3));
+        assert!(!span.contains(4));
+    }
+
+    #[test]
+    fn merge_and_order() {
+        let left = Span::new(Position::new(0, 0, 0), Position::new(2, 0, 2));
+        let right = Span::new(Position::new(5, 1, 0), Position::new(7, 1, 2));
+        assert!(left < right);
+        assert_eq!(left.merge(right),
+                   Span::new(Position::new(0, 0, 0), Position::new(7, 1, 2)));
+        assert_eq!(right.merge(left), left.merge(right));
+    }
+
+    #[test]
+    fn shifted() {
+        let base = Position::new(10, 3, 4);
+        assert_eq!(Span::of("ab").shifted(base),
+                   Span::new(base, Position::new(12, 3, 6)));
+        assert_eq!(Span::of("a\nb").shifted(base),
+                   Span::new(base, Position::new(13, 4, 1)));
+    }
+
+    #[test]
+    fn lines() {
+        let base = Position::new(10, 3, 4);
+        assert_eq!(Span::of("ab").shifted(base).lines("ab"),
+                   vec![Span::new(base, Position::new(12, 3, 6))]);
+        assert_eq!(Span::of("a\r\nbc\n").shifted(base).lines("a\r\nbc\n"),
+                   vec![Span::new(base, Position::new(11, 3, 5)),
+                        Span::new(Position::new(13, 4, 0), Position::new(15, 4, 2))]);
+        assert_eq!(Span::of("").lines(""), vec![Span::default()]);
+    }
+
+    #[test]
+    fn long_lines() {
+        let line = "x".repeat(70000);
+        assert_eq!(Span::of(&line).end, Position::new(70000, 0, 70000));
+    }
+}
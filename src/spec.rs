@@ -0,0 +1,257 @@
+//! Lexer specifications, in the spirit of the `.x` files of Alex: the
+//! lexemes are described in a text file, which is loaded at runtime into
+//! a `CombinedAutomaton`, so that a dialect or an extension of Haskell can
+//! be tried without recompiling. `haskell.lex` describes the lexemes of
+//! `driver::definitions()` this way.
+//!
+//! ```text
+//! -- a comment
+//! $digit = 0-9                        -- a character class
+//! $hexit = A-Fa-f$digit
+//! @hexadecimal = 0[xX][$hexit]+        -- a macro
+//! IntegerLiteral : @hexadecimal        -- a rule
+//! QVarId : [a-z]+ except (if|then)     -- a rule with an exception
+//! MLComment : %mlcomment              -- a hand-written automaton
+//! ```
+//!
+//! - `$name = ...` names the inside of a character group, so it is used
+//!   as `[$digit]`, or inside of another class, like `$hexit` does;
+//! - `@name = ...` names an expression, it is pasted as it is, the same
+//!   way `reused` builds its classes with `format!`;
+//! - `TokenType : expression` is a rule, the name is a variant of
+//!   `token::TokenType`; what follows ` except ` is left out, when it
+//!   covers a whole match, like `RegexLexeme::except_for()`;
+//! - `TokenType : %name` uses a hand-written automaton instead, either
+//!   `%mlcomment`, `%string` or `%pragma`;
+//! - the rules are listed by priority, the first one wins on matches of
+//!   equal length;
+//! - names are made of ASCII letters, digits and `_`, and `\$` or `\@`
+//!   is not taken for a name.
+
+use crate::combined;
+use crate::dfa;
+use crate::mlcomment;
+use crate::pragma;
+use crate::spec_syntax;
+use crate::string;
+use crate::token;
+
+use regex as regex_backend;
+
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// A line that is neither a definition, nor a rule, nor a comment.
+    Syntax { line : usize },
+    UndefinedName { line : usize, name : String },
+    UnknownTokenType { line : usize, name : String },
+    UnknownAutomaton { line : usize, name : String },
+    InvalidExpression { line : usize, error : regex_backend::Error },
+    /// The rules are fine one by one, but not together.
+    Automaton(combined::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "cannot read the specification: {}", error),
+            Error::Syntax { line } =>
+                write!(f, "line {}: expected a class, a macro or a rule", line),
+            Error::UndefinedName { line, name } =>
+                write!(f, "line {}: `{}` is not defined", line, name),
+            Error::UnknownTokenType { line, name } =>
+                write!(f, "line {}: `{}` is not a token type", line, name),
+            Error::UnknownAutomaton { line, name } =>
+                write!(f, "line {}: there is no automaton `%{}`", line, name),
+            Error::InvalidExpression { line, error } =>
+                write!(f, "line {}: invalid expression: {}", line, error),
+            Error::Automaton(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<spec_syntax::Error> for Error {
+    fn from(error : spec_syntax::Error) -> Self {
+        match error {
+            spec_syntax::Error::Syntax { line } => Error::Syntax { line },
+            spec_syntax::Error::UndefinedName { line, name } => Error::UndefinedName { line, name },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::InvalidExpression { error, .. } => Some(error),
+            Error::Automaton(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// How a rule recognizes its lexeme.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Body {
+    Regex { expression : String, except_for : Option<String> },
+    /// The name of a hand-written automaton, without the `%`.
+    Automaton(String),
+}
+
+/// A rule, with its macros and classes expanded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub token_type : token::TokenType,
+    pub body : Body,
+}
+
+/// A parsed specification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spec {
+    /// By priority.
+    pub rules : Vec<Rule>,
+}
+
+impl Spec {
+    pub fn load(path : impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    pub fn parse(text : &str) -> Result<Self, Error> {
+        let mut rules = vec![];
+
+        for definition in spec_syntax::parse(text)? {
+            match definition {
+                spec_syntax::Definition::Class { line, body } =>
+                    check(&format!("[{}]", body), line)?,
+                spec_syntax::Definition::Rule { line, name, body } => {
+                    let token_type = name.parse()
+                        .map_err(|_| Error::UnknownTokenType { line, name : name.to_string() })?;
+                    let body = match body {
+                        spec_syntax::Body::Automaton(automaton) => {
+                            if automaton_of(automaton).is_none() {
+                                return Err(Error::UnknownAutomaton { line,
+                                                                     name : automaton.to_string() })
+                            }
+                            Body::Automaton(automaton.to_string())
+                        },
+                        spec_syntax::Body::Regex { expression, except_for } => {
+                            check(&expression, line)?;
+                            if let Some(except_for) = &except_for {
+                                check(except_for, line)?;
+                            }
+                            Body::Regex { expression, except_for }
+                        },
+                    };
+                    rules.push(Rule { token_type, body });
+                },
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// The definitions of the rules, for a `CombinedAutomaton`.
+    pub fn definitions(&self) -> Vec<combined::Definition> {
+        self.rules.iter().map(|rule| match &rule.body {
+            Body::Regex { expression, except_for } => combined::Definition::Regex {
+                token_type : rule.token_type,
+                expression : expression.clone(),
+                except_for : except_for.clone(),
+            },
+            Body::Automaton(name) => combined::Definition::Automaton {
+                token_type : rule.token_type,
+                // the names were checked when parsing
+                start : automaton_of(name).unwrap(),
+            },
+        }).collect()
+    }
+
+    /// Builds the automaton of the rules, a `driver::Lexer` uses it with
+    /// `with_lexemes()`.
+    pub fn automaton(&self) -> Result<combined::CombinedAutomaton, Error> {
+        combined::CombinedAutomaton::new(self.definitions()).map_err(Error::Automaton)
+    }
+}
+
+/// The hand-written automata that rules may use.
+fn automaton_of(name : &str) -> Option<fn() -> Box<dyn dfa::CharDFA>> {
+    match name {
+        "mlcomment" => Some(|| Box::new(mlcomment::MLComment::new())),
+        "string" => Some(|| Box::new(string::StringLiteral::new())),
+        "pragma" => Some(|| Box::new(pragma::Pragma::new())),
+        _ => None,
+    }
+}
+
+fn check(expression : &str, line : usize) -> Result<(), Error> {
+    regex_backend::Regex::new(expression)
+        .map(|_| ())
+        .map_err(|error| Error::InvalidExpression { line, error })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::driver;
+
+    const HASKELL : &str = include_str!("haskell.lex");
+
+    #[test]
+    fn haskell() {
+        let spec = Spec::parse(HASKELL).unwrap();
+        assert_eq!(combined::patterns(&spec.definitions()),
+                   combined::patterns(&driver::definitions()));
+        assert_eq!(spec.definitions().iter().map(|definition| definition.token_type())
+                   .collect::<Vec<_>>(),
+                   driver::definitions().iter().map(|definition| definition.token_type())
+                   .collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dialect() {
+        // the Haskell lexemes, with binary literals
+        let text = format!("{}\nIntegerLiteral : 0[bB][01]+\n", HASKELL);
+        let automaton = Spec::parse(&text).unwrap().automaton().unwrap();
+        let types : Vec<_> = driver::Lexer::new("x = 0b101 {- y -}")
+            .with_lexemes(&automaton)
+            .map(|token| token.unwrap().token_type)
+            .collect();
+        use token::TokenType::*;
+        assert_eq!(types, vec![QVarId, ReservedOp, IntegerLiteral, MLComment]);
+    }
+
+    #[test]
+    fn expansion() {
+        let spec = Spec::parse("\
+$digit = 0-9
+$hexit = A-Fa-f$digit   -- a comment
+@hex = 0x[$hexit]+
+IntegerLiteral : @hex|[$digit]+ except 0
+QVarSym : [-]--|\\$x|\\@b|@=
+").unwrap();
+        assert_eq!(spec.rules[0].body, Body::Regex {
+            expression : "0x[A-Fa-f0-9]+|[0-9]+".to_string(),
+            except_for : Some("0".to_string()),
+        });
+        assert_eq!(spec.rules[1].body, Body::Regex {
+            expression : "[-]--|\\$x|\\@b|@=".to_string(),
+            except_for : None,
+        });
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text : &str| Spec::parse(text).unwrap_err().to_string();
+        assert_eq!(error("\n$a = x\nQVarId : [$b]"), "line 3: `$b` is not defined");
+        assert_eq!(error("Foo : x"), "line 1: `Foo` is not a token type");
+        assert_eq!(error("QVarId : %foo"), "line 1: there is no automaton `%foo`");
+        assert_eq!(error("what"), "line 1: expected a class, a macro or a rule");
+        assert_eq!(error("$a b = x"), "line 1: expected a class, a macro or a rule");
+        assert!(error("QVarId : (x").starts_with("line 1: invalid expression"));
+        assert!(matches!(Spec::load("does/not/exist.lex"), Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,160 @@
+//! The text of a lexer specification, see `spec` for the format: the
+//! comments are cut off and the classes and macros are expanded, but the
+//! names of the rules and the expressions are not checked. `build.rs`
+//! reads `haskell.lex` with it too, so it uses nothing of the crate.
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// A line that is neither a definition, nor a rule, nor a comment.
+    Syntax { line : usize },
+    UndefinedName { line : usize, name : String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax { line } =>
+                write!(f, "line {}: expected a class, a macro or a rule", line),
+            Error::UndefinedName { line, name } =>
+                write!(f, "line {}: `{}` is not defined", line, name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// How a rule recognizes its lexeme.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Body<'a> {
+    Regex { expression : String, except_for : Option<String> },
+    /// The name of a hand-written automaton, without the `%`.
+    Automaton(&'a str),
+}
+
+/// A line that defines something, with the classes and macros it uses
+/// expanded. Macros are pasted into the rules, they are not listed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Definition<'a> {
+    /// The inside of a character group.
+    Class { line : usize, body : String },
+    Rule { line : usize, name : &'a str, body : Body<'a> },
+}
+
+/// The definitions of `text`, in the order they are written.
+pub fn parse(text : &str) -> Result<Vec<Definition<'_>>, Error> {
+    let mut classes : HashMap<&str, String> = HashMap::new();
+    let mut macros : HashMap<&str, String> = HashMap::new();
+    let mut definitions = vec![];
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue
+        }
+
+        if let Some(definition) = line.strip_prefix('$') {
+            let (name, body) = definition_of(definition, number)?;
+            let body = expand(body, &classes, &macros, number)?;
+            classes.insert(name, body.clone());
+            definitions.push(Definition::Class { line : number, body });
+        } else if let Some(definition) = line.strip_prefix('@') {
+            let (name, body) = definition_of(definition, number)?;
+            let body = expand(body, &classes, &macros, number)?;
+            macros.insert(name, body);
+        } else {
+            let (name, body) = line.split_once(':').ok_or(Error::Syntax { line : number })?;
+            let body = body.trim();
+            let body = if let Some(automaton) = body.strip_prefix('%') {
+                Body::Automaton(automaton)
+            } else {
+                let (expression, except_for) = match body.split_once(" except ") {
+                    Some((expression, except_for)) => (expression.trim(), Some(except_for.trim())),
+                    None => (body, None),
+                };
+                Body::Regex {
+                    expression : expand(expression, &classes, &macros, number)?,
+                    except_for : except_for
+                        .map(|except_for| expand(except_for, &classes, &macros, number))
+                        .transpose()?,
+                }
+            };
+            definitions.push(Definition::Rule { line : number, name : name.trim(), body });
+        }
+    }
+
+    Ok(definitions)
+}
+
+/// Cuts a `--` comment off, unless it is a part of an expression, as in
+/// `[-]--`, where the dashes follow something else than whitespace.
+fn strip_comment(line : &str) -> &str {
+    let mut previous = ' ';
+    for (offset, character) in line.char_indices() {
+        if previous.is_whitespace() && line[offset..].starts_with("--") {
+            return &line[..offset]
+        }
+        previous = character;
+    }
+    line
+}
+
+/// Splits `name = body`.
+fn definition_of(definition : &str, line : usize) -> Result<(&str, &str), Error> {
+    let (name, body) = definition.split_once('=').ok_or(Error::Syntax { line })?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        return Err(Error::Syntax { line })
+    }
+    Ok((name, body.trim()))
+}
+
+fn is_name_char(character : char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_'
+}
+
+/// Replaces the `$class` and `@macro` names in `body`.
+fn expand(body : &str, classes : &HashMap<&str, String>, macros : &HashMap<&str, String>,
+          line : usize) -> Result<String, Error> {
+    let mut expanded = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(character) = rest.chars().next() {
+        if character == '\\' {
+            // an escape, the next character is kept as it is
+            let escape = rest.chars().nth(1).map_or(1, |next| 1 + next.len_utf8());
+            expanded += &rest[..escape];
+            rest = &rest[escape..];
+            continue
+        }
+
+        let name_length = rest[1..].find(|character| !is_name_char(character))
+            .unwrap_or(rest.len() - 1);
+        let definitions = match character {
+            '$' => Some(classes),
+            '@' => Some(macros),
+            _ => None,
+        };
+        match definitions {
+            Some(definitions) if name_length > 0
+                && !rest[1..].starts_with(|character : char| character.is_ascii_digit()) => {
+                let name = &rest[1..1 + name_length];
+                match definitions.get(name) {
+                    Some(definition) => expanded += definition,
+                    None => return Err(Error::UndefinedName {
+                        line,
+                        name : format!("{}{}", character, name),
+                    }),
+                }
+                rest = &rest[1 + name_length..];
+            },
+            _ => {
+                expanded.push(character);
+                rest = &rest[character.len_utf8()..];
+            },
+        }
+    }
+    Ok(expanded)
+}
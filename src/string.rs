@@ -0,0 +1,308 @@
+//! String literals, read by a hand-written automaton rather than by an
+//! expression, so that a broken string can be told apart from input that
+//! is not a string at all, and explained.
+//!
+//! string -> " { graphic w/o{ " | \ } | space | escape | gap } "
+//! gap -> \ whitechar { whitechar } \
+//!
+//! The escapes are the ones of chars, see `literals`, and `\&` too, which
+//! stands for nothing, as in `"\SO\&H"`.
+
+use crate::dfa;
+use crate::lexeme;
+use crate::literals;
+use crate::reused;
+use crate::token;
+
+use std::fmt;
+
+/// Why a string that was opened is broken.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Error {
+    /// The line ends before the closing quote, only gaps span lines.
+    EndOfLine,
+    /// The input ends before the closing quote.
+    EndOfInput,
+    /// A gap holds something else than whitespace before its backslash.
+    UnterminatedGap,
+    InvalidEscape,
+    /// A character that may only be written as an escape, like a tab.
+    UnescapedCharacter,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EndOfLine => write!(f, "unterminated string, the line ends before it does"),
+            Error::EndOfInput => write!(f, "unterminated string, the input ends before it does"),
+            Error::UnterminatedGap => write!(f, "the string gap is not closed with a backslash"),
+            Error::InvalidEscape => write!(f, "invalid escape sequence in the string"),
+            Error::UnescapedCharacter => write!(f, "the character has to be escaped in a string"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The names of the control characters, as in `\NUL`, by code point, but
+/// for `DEL`. The char literals of `literals` are made of them too, only
+/// `@ascii` of `haskell.lex` spells them out again.
+pub(crate) const ASCII : [&str; 34] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "HT", "LF", "VT",
+    "FF", "CR", "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB",
+    "CAN", "EM", "SUB", "ESC", "FS", "GS", "RS", "US", "SP", "DEL",
+];
+
+/// String literal DFA.
+pub struct StringLiteral {
+    state : StringState,
+}
+
+impl StringLiteral {
+    pub fn new() -> Self {
+        Self { state : StringState::Initial }
+    }
+}
+
+impl Default for StringLiteral {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StringState {
+    Initial,
+    Inside,
+    SawBackslash,
+    /// After `\^`.
+    SawCaret,
+    /// Inside of a name such as `\DEL`, the letters so far.
+    AsciiName([u8; 3], usize),
+    Decimal,
+    /// After `\o` or `\x`, before the first digit.
+    OctalStart,
+    Octal,
+    HexadecimalStart,
+    Hexadecimal,
+    Gap,
+    Final,
+    /// `None` when the input is not a string at all.
+    FailedMatch(Option<Error>),
+}
+
+impl StringState {
+    /// Where a character of the string leads, when it is not a part of an
+    /// escape.
+    fn inside(input : char) -> Self {
+        match input {
+            '"' => Self::Final,
+            '\\' => Self::SawBackslash,
+            '\n' | '\r' | '\x0C' => Self::FailedMatch(Some(Error::EndOfLine)),
+            _ if is_plain(input) => Self::Inside,
+            _ => Self::FailedMatch(Some(Error::UnescapedCharacter)),
+        }
+    }
+}
+
+/// Tells if `character` may be written as it is in a string, ASCII does
+/// not go through the regex.
+fn is_plain(character : char) -> bool {
+    ::lazy_static::lazy_static! {
+        static ref PLAIN : ::regex::Regex = ::regex::Regex::new(
+            &format!("^[{}]$", literals::graphic())).unwrap();
+    }
+
+    if character.is_ascii() {
+        matches!(character, ' '..='~') && character != '"' && character != '\\'
+    } else {
+        let mut buffer = [0; 4];
+        PLAIN.is_match(character.encode_utf8(&mut buffer))
+    }
+}
+
+impl dfa::DFA for StringLiteral {
+
+    type Input = char;
+
+    type State = StringState;
+
+    fn in_final_state(&self) -> bool {
+        self.state == Self::State::Final
+    }
+
+    fn in_fail_state(&self) -> bool {
+        matches!(self.state, Self::State::FailedMatch(_))
+    }
+
+    /// the opening quote was seen, but not the closing one
+    fn is_incomplete(&self) -> bool {
+        !matches!(self.state,
+                  Self::State::Initial | Self::State::Final | Self::State::FailedMatch(_))
+    }
+
+    fn error(&self) -> Option<lexeme::Error> {
+        match &self.state {
+            Self::State::FailedMatch(Some(error)) => Some(lexeme::Error::String(*error)),
+            _ if self.is_incomplete() => Some(lexeme::Error::String(Error::EndOfInput)),
+            _ => None,
+        }
+    }
+
+    fn advance(&mut self, input : Self::Input) {
+        self.state = match self.state {
+
+            // Final/Failed
+            Self::State::Final => Self::State::Final,
+            failed @ Self::State::FailedMatch(_) => failed,
+
+            Self::State::Initial if input == '"' => Self::State::Inside,
+            Self::State::Initial => Self::State::FailedMatch(None),
+
+            Self::State::Inside => Self::State::inside(input),
+
+            // SawBackslash -> *
+            Self::State::SawBackslash => match input {
+                'a' | 'b' | 'f' | 'n' | 'r' | 't' | 'v' | '\\' | '"' | '\'' | '&' =>
+                    Self::State::Inside,
+                '^' => Self::State::SawCaret,
+                'o' => Self::State::OctalStart,
+                'x' => Self::State::HexadecimalStart,
+                '0'..='9' => Self::State::Decimal,
+                'A'..='Z' => Self::State::AsciiName([input as u8, 0, 0], 1),
+                _ if reused::is_whitechar(input) => Self::State::Gap,
+                _ => Self::State::FailedMatch(Some(Error::InvalidEscape)),
+            },
+
+            Self::State::SawCaret => match input {
+                'A'..='Z' | '@' | '[' | '\\' | ']' | '^' | '_' => Self::State::Inside,
+                _ => Self::State::FailedMatch(Some(Error::InvalidEscape)),
+            },
+
+            // the longest name wins, `\SOH` is not `\SO` and `H`
+            Self::State::AsciiName(mut name, len) => {
+                let longer = len < name.len() && input.is_ascii() && {
+                    name[len] = input as u8;
+                    ASCII.iter().any(|ascii| ascii.as_bytes().starts_with(&name[..=len]))
+                };
+                if longer {
+                    Self::State::AsciiName(name, len + 1)
+                } else if ASCII.iter().any(|ascii| ascii.as_bytes() == &name[..len]) {
+                    Self::State::inside(input)
+                } else {
+                    Self::State::FailedMatch(Some(Error::InvalidEscape))
+                }
+            },
+
+            // the numbers end at the first character that is not a digit
+            Self::State::Decimal if input.is_ascii_digit() => Self::State::Decimal,
+            Self::State::OctalStart | Self::State::Octal if matches!(input, '0'..='7') =>
+                Self::State::Octal,
+            Self::State::HexadecimalStart | Self::State::Hexadecimal
+                if input.is_ascii_hexdigit() => Self::State::Hexadecimal,
+            Self::State::OctalStart | Self::State::HexadecimalStart =>
+                Self::State::FailedMatch(Some(Error::InvalidEscape)),
+            Self::State::Decimal | Self::State::Octal | Self::State::Hexadecimal =>
+                Self::State::inside(input),
+
+            // Gap -> *
+            Self::State::Gap if input == '\\' => Self::State::Inside,
+            Self::State::Gap if reused::is_whitechar(input) => Self::State::Gap,
+            Self::State::Gap => Self::State::FailedMatch(Some(Error::UnterminatedGap)),
+        }
+    }
+}
+
+impl lexeme::Lexeme for StringLiteral {
+
+    fn recognize(input : &str) -> Result<token::Token<'_>, lexeme::Error> {
+        dfa::recognize_with(StringLiteral::new(), token::TokenType::StringLiteral, input)
+    }
+}
+
+/// How much of `input`, which starts with a broken string, belongs to the
+/// string: up to the closing quote, or else to the end of the line. The
+/// escaped quotes and line breaks do not count.
+pub fn extent(input : &str) -> usize {
+    let mut chars = input.char_indices().skip(1);
+    while let Some((offset, character)) = chars.next() {
+        match character {
+            '"' => return offset + 1,
+            '\n' | '\r' | '\x0C' => return offset,
+            '\\' => { chars.next(); },
+            _ => {},
+        }
+    }
+    input.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::lexeme::Lexeme;
+    use crate::span;
+
+    fn string(input : &str) -> Result<&str, lexeme::Error> {
+        StringLiteral::recognize(input).map(|token| token.text)
+    }
+
+    fn broken(input : &str) -> Error {
+        match StringLiteral::recognize(input) {
+            Err(lexeme::Error::String(error)) => error,
+            other => panic!("{:?} is not a broken string: {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn lexeme() {
+        for literal in &["\"\"", "\"abc\"", "\"a b 'c'\"", "\"\u{3bb} \u{2192}\"",
+                         r#""\"\\\'""#, r#""\n\t\&\^A\^[\NUL\SOH\SO\&H\DEL""#,
+                         r#""\1114111\o777\xFFab""#, r#""\1234x""#, r#""\SOHX""#] {
+            assert_eq!(string(literal), Ok(*literal));
+        }
+        assert_eq!(string("\"a\" \"b\""), Ok("\"a\""));
+        assert_eq!(string("x"), Err(lexeme::Error::Automata(dfa::Error::IsInFailState)));
+        assert_eq!(string(""), Err(lexeme::Error::NotRecognized));
+    }
+
+    #[test]
+    fn gaps() {
+        assert_eq!(string("\"ab\\   \\cd\""), Ok("\"ab\\   \\cd\""));
+        assert_eq!(string("\"ab\\\n   \\cd\" x"), Ok("\"ab\\\n   \\cd\""));
+        assert_eq!(string("\"\\\r\n\t\\\""), Ok("\"\\\r\n\t\\\""));
+
+        // the span runs over the lines of the gap
+        let token = StringLiteral::recognize("\"ab\\\n  \\cd\"").unwrap();
+        assert_eq!(token.span,
+                   span::Span::new(span::Position::new(0, 0, 0),
+                                   span::Position::new(11, 1, 6)));
+        assert_eq!(token.line_spans(), vec![
+            span::Span::new(span::Position::new(0, 0, 0), span::Position::new(4, 0, 4)),
+            span::Span::new(span::Position::new(5, 1, 0), span::Position::new(11, 1, 6)),
+        ]);
+    }
+
+    #[test]
+    fn broken_strings() {
+        assert_eq!(broken("\"abc"), Error::EndOfInput);
+        assert_eq!(broken("\"abc\\"), Error::EndOfInput);
+        assert_eq!(broken("\"abc\\\n  "), Error::EndOfInput);
+        assert_eq!(broken("\"abc\ndef\""), Error::EndOfLine);
+        assert_eq!(broken("\"abc\r\n"), Error::EndOfLine);
+        assert_eq!(broken("\"ab\\  cd\\\""), Error::UnterminatedGap);
+        assert_eq!(broken("\"\\q\""), Error::InvalidEscape);
+        assert_eq!(broken("\"\\^a\""), Error::InvalidEscape);
+        assert_eq!(broken("\"\\SX\""), Error::InvalidEscape);
+        assert_eq!(broken("\"\\xg\""), Error::InvalidEscape);
+        assert_eq!(broken("\"\\o\""), Error::InvalidEscape);
+        assert_eq!(broken("\"a\tb\""), Error::UnescapedCharacter);
+        assert_eq!(broken("\"a\u{200b}b\""), Error::UnescapedCharacter);
+    }
+
+    #[test]
+    fn extents() {
+        assert_eq!(extent("\"a\\qb\" x"), 6);
+        assert_eq!(extent("\"a\\\"b\nx\""), 5);
+        assert_eq!(extent("\"ab"), 3);
+    }
+}
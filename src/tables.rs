@@ -0,0 +1,51 @@
+//! The tables of the combined automaton of `driver::definitions()`, made
+//! by `build.rs`, so that the lexer does not compile any expression when
+//! it starts.
+
+include!(concat!(env!("OUT_DIR"), "/patterns.rs"));
+
+// the tables are read in place, they have to be aligned as `u32`s are
+#[repr(C)]
+struct Aligned<B : ?Sized> {
+    _align : [u32; 0],
+    bytes : B,
+}
+
+#[cfg(target_endian = "little")]
+static DFA : &Aligned<[u8]> = &Aligned {
+    _align : [],
+    bytes : *include_bytes!(concat!(env!("OUT_DIR"), "/lexemes.le.dfa")),
+};
+
+#[cfg(target_endian = "big")]
+static DFA : &Aligned<[u8]> = &Aligned {
+    _align : [],
+    bytes : *include_bytes!(concat!(env!("OUT_DIR"), "/lexemes.be.dfa")),
+};
+
+/// The serialized DFA, see `CombinedAutomaton::from_tables`.
+pub fn dfa() -> &'static [u8] {
+    &DFA.bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::combined;
+    use crate::driver;
+
+    #[test]
+    fn agree_with_the_definitions() {
+        let definitions = driver::definitions();
+        assert_eq!(combined::patterns(&definitions), PATTERNS);
+
+        let automaton = combined::CombinedAutomaton::new(definitions).unwrap();
+        assert_eq!(automaton.to_bytes(), dfa());
+    }
+
+    #[test]
+    fn other_definitions() {
+        assert!(matches!(combined::CombinedAutomaton::from_tables(vec![], dfa()),
+                         Err(combined::Error::TablesMismatch)));
+    }
+}
@@ -0,0 +1,20 @@
+//! What the tests of several modules share.
+
+/// A tiny xorshift generator, so that the property tests are reproducible
+/// without pulling a dependency in.
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    pub(crate) fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+
+    /// Up to `max_pieces` of `pieces`, one after another.
+    pub(crate) fn pieces(&mut self, pieces : &[&str], max_pieces : usize) -> String {
+        let length = self.next() % (max_pieces + 1);
+        (0..length).map(|_| pieces[self.next() % pieces.len()]).collect()
+    }
+}
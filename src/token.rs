@@ -1,4 +1,10 @@
-#[derive(PartialEq, PartialOrd, Debug)]
+use crate::span;
+use crate::comment;
+use crate::lexeme;
+use crate::pragma;
+use crate::value;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum TokenType {
     Ident,
     MLComment,
@@ -13,15 +19,216 @@ pub enum TokenType {
     IntegerLiteral,
     FloatLiteral,
     CharLiteral,
+    StringLiteral,
+    /// A pragma, as in `{-# LANGUAGE GADTs #-}`, see `Payload::Pragma`.
+    Pragma,
     BigASCII,
     LowASCII,
     Digit,
     Octit,
     Hexit,
+    UniSmall,
+    UniLarge,
+    UniDigit,
+    UniSymbol,
+    UniWhite,
+    Small,
+    Large,
+    Symbol,
+    WhiteChar,
+    /// Input that could not be lexed, see `Payload::Error` for why.
+    Error,
+    /// A run of whitechars other than newlines, only in lossless mode.
+    Whitespace,
+    /// One newline, only in lossless mode.
+    Newline,
+    /// Holds the trivia at the end of file, only in lossless mode.
+    EndOfFile,
+}
+
+impl TokenType {
+    /// Tells if tokens of this type are trivia, which a parser skips but
+    /// a formatter has to keep: whitespace and comments.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self,
+                 TokenType::Whitespace
+                 | TokenType::Newline
+                 | TokenType::MLComment
+                 | TokenType::SLComment)
+    }
+}
+
+/// Reads the name of a variant, as in `TokenType::QVarId`, which is how
+/// lexer specifications name the token types.
+impl std::str::FromStr for TokenType {
+    type Err = ();
+
+    fn from_str(name : &str) -> Result<Self, ()> {
+        use TokenType::*;
+        Ok(match name {
+            "Ident" => Ident,
+            "MLComment" => MLComment,
+            "SLComment" => SLComment,
+            "ReservedId" => ReservedId,
+            "ReservedOp" => ReservedOp,
+            "QConId" => QConId,
+            "QVarId" => QVarId,
+            "QVarSym" => QVarSym,
+            "QConSym" => QConSym,
+            "Special" => Special,
+            "IntegerLiteral" => IntegerLiteral,
+            "FloatLiteral" => FloatLiteral,
+            "CharLiteral" => CharLiteral,
+            "StringLiteral" => StringLiteral,
+            "Pragma" => Pragma,
+            "BigASCII" => BigASCII,
+            "LowASCII" => LowASCII,
+            "Digit" => Digit,
+            "Octit" => Octit,
+            "Hexit" => Hexit,
+            "UniSmall" => UniSmall,
+            "UniLarge" => UniLarge,
+            "UniDigit" => UniDigit,
+            "UniSymbol" => UniSymbol,
+            "UniWhite" => UniWhite,
+            "Small" => Small,
+            "Large" => Large,
+            "Symbol" => Symbol,
+            "WhiteChar" => WhiteChar,
+            "Error" => Error,
+            "Whitespace" => Whitespace,
+            "Newline" => Newline,
+            "EndOfFile" => EndOfFile,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// What a token knows beyond its type and span.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub enum Payload {
+    Error(lexeme::Error),
+    /// The value of a literal.
+    Literal(value::LiteralValue),
+    /// What a Haddock comment documents.
+    Doc(comment::DocKind),
+    /// The name and the arguments of a pragma.
+    Pragma(pragma::Contents),
 }
 
-#[derive(PartialEq, PartialOrd, Debug)]
-pub struct Token {
-    pub span : Vec<u16>,
+/// A token that borrows its text from the source code, so lexing does
+/// not allocate. `into_owned()` makes an `OwnedToken` to keep it around
+/// after the source is gone.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub struct Token<'src> {
+    pub span : span::Span,
     pub token_type : TokenType,
+    /// The part of the source code the token was made of.
+    pub text : &'src str,
+    pub payload : Option<Payload>,
+}
+
+impl<'src> Token<'src> {
+    pub fn new(token_type : TokenType, span : span::Span, text : &'src str) -> Self {
+        Self { span, token_type, text, payload : None }
+    }
+
+    /// Why the input could not be lexed, for `TokenType::Error` tokens.
+    pub fn error(&self) -> Option<&lexeme::Error> {
+        match &self.payload {
+            Some(Payload::Error(reason)) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// The span of every line the token runs over, without the line
+    /// breaks, for tokens such as strings with gaps and block comments.
+    pub fn line_spans(&self) -> Vec<span::Span> {
+        self.span.lines(self.text)
+    }
+
+    /// The value of a literal token.
+    pub fn literal(&self) -> Option<&value::LiteralValue> {
+        match &self.payload {
+            Some(Payload::Literal(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The kind of a Haddock comment.
+    pub fn doc(&self) -> Option<&comment::DocKind> {
+        match &self.payload {
+            Some(Payload::Doc(kind)) => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// What a pragma says.
+    pub fn pragma(&self) -> Option<&pragma::Contents> {
+        match &self.payload {
+            Some(Payload::Pragma(contents)) => Some(contents),
+            _ => None,
+        }
+    }
+
+    pub fn into_owned(self) -> OwnedToken {
+        OwnedToken {
+            span : self.span,
+            token_type : self.token_type,
+            text : self.text.to_string(),
+            payload : self.payload,
+        }
+    }
+}
+
+/// A token that owns its text, for storage.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub struct OwnedToken {
+    pub span : span::Span,
+    pub token_type : TokenType,
+    pub text : String,
+    pub payload : Option<Payload>,
+}
+
+impl OwnedToken {
+    /// Borrows the token back, with the text borrowed from this one.
+    pub fn as_token(&self) -> Token<'_> {
+        Token {
+            span : self.span,
+            token_type : self.token_type,
+            text : &self.text,
+            payload : self.payload.clone(),
+        }
+    }
+}
+
+impl<'src> From<Token<'src>> for OwnedToken {
+    fn from(token : Token<'src>) -> Self {
+        token.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn owned() {
+        let source = String::from("where");
+        let owned = {
+            let token = Token::new(TokenType::ReservedId, span::Span::of(&source), &source);
+            token.into_owned()
+        };
+        drop(source);
+        assert_eq!(owned.text, "where");
+        assert_eq!(owned.as_token().text, "where");
+        assert_eq!(owned.as_token().into_owned(), owned);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("QVarId".parse(), Ok(TokenType::QVarId));
+        assert_eq!("EndOfFile".parse(), Ok(TokenType::EndOfFile));
+        assert_eq!("qvarid".parse::<TokenType>(), Err(()));
+    }
 }
@@ -0,0 +1,160 @@
+//! Lossless token stream: whitespace, newlines and comments are kept as
+//! trivia, attached to the tokens around them, so that the source code can
+//! be reproduced byte for byte, which formatters and refactoring tools
+//! need.
+//!
+//! A token owns the trivia that follows it on the same line, up to and
+//! including the newline, as its trailing trivia. Everything else is the
+//! leading trivia of the next token. The trivia at the end of file belongs
+//! to a final `TokenType::EndOfFile` token.
+
+use crate::driver;
+use crate::span;
+use crate::token;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FullToken<'src> {
+    pub leading : Vec<token::Token<'src>>,
+    pub token : token::Token<'src>,
+    pub trailing : Vec<token::Token<'src>>,
+}
+
+impl<'src> FullToken<'src> {
+    /// The span of the token with its trivia.
+    pub fn full_span(&self) -> span::Span {
+        let start = self.leading.first().unwrap_or(&self.token).span;
+        let end = self.trailing.last().unwrap_or(&self.token).span;
+        start.merge(end)
+    }
+
+    /// The text of the token with its trivia.
+    pub fn full_text(&self) -> String {
+        self.leading.iter()
+            .chain(std::iter::once(&self.token))
+            .chain(self.trailing.iter())
+            .map(|token| token.text)
+            .collect()
+    }
+}
+
+/// Splits all of `source` into tokens with trivia. Errors are reported as
+/// `TokenType::Error` tokens, so this never fails.
+pub fn tokenize(source : &str) -> Vec<FullToken<'_>> {
+    let mut tokens : Vec<FullToken<'_>> = vec![];
+    let mut leading = vec![];
+    // the last token still takes trivia, until a newline
+    let mut takes_trailing = false;
+
+    for token in driver::Lexer::lossless(source) {
+        // a lossless lexer recovers from errors
+        let token = token.unwrap();
+        if token.token_type.is_trivia() {
+            match tokens.last_mut() {
+                Some(last) if takes_trailing => {
+                    takes_trailing = token.token_type != token::TokenType::Newline;
+                    last.trailing.push(token);
+                },
+                _ => leading.push(token),
+            }
+        } else {
+            tokens.push(FullToken { leading : std::mem::take(&mut leading),
+                                    token,
+                                    trailing : vec![] });
+            takes_trailing = true;
+        }
+    }
+
+    let end = span::Position::default().advance(source);
+    tokens.push(FullToken {
+        leading,
+        token : token::Token::new(token::TokenType::EndOfFile,
+                                  span::Span::new(end, end), ""),
+        trailing : vec![],
+    });
+    tokens
+}
+
+/// Concatenates the text of the tokens and their trivia, for tokens made
+/// by `tokenize(source)` this gives `source` back.
+pub fn reconstruct(tokens : &[FullToken<'_>]) -> String {
+    tokens.iter().map(|full| full.full_text()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util;
+
+    const CORPUS : [&str; 8] = [
+        "",
+        "   \n\t",
+        "module Main where\n\nimport Data.List (sort)\n",
+        "main = print (sort [3, 1, 2]) {- done -}\r\n",
+        "f x = x + 1 -- not a comment yet\n  where y = \u{3bb}\u{2192}\u{3a3}\n",
+        "{- outer {- inner -} -}\n\x0C\n  data T = T\u{a0}\u{3000}",
+        "x = \"broken\u{200b} ` ? {- unterminated\n",
+        "a\r\rb\n\n",
+    ];
+
+    fn assert_lossless(source : &str) {
+        let tokens = tokenize(source);
+        assert_eq!(reconstruct(&tokens), source);
+
+        // the pieces are in order, without gaps or overlaps
+        let mut position = span::Position::default();
+        for full in &tokens {
+            for token in full.leading.iter()
+                .chain(std::iter::once(&full.token))
+                .chain(full.trailing.iter()) {
+                assert_eq!(token.span.start, position, "{:?}", source);
+                assert_eq!(token.text, &source[token.span.start.offset..token.span.end.offset]);
+                position = token.span.end;
+            }
+        }
+        assert_eq!(position, span::Position::default().advance(source));
+        assert_eq!(tokens.last().unwrap().token.token_type,
+                   token::TokenType::EndOfFile);
+    }
+
+    #[test]
+    fn corpus() {
+        for source in &CORPUS {
+            assert_lossless(source);
+        }
+    }
+
+    #[test]
+    fn attachment() {
+        let source = "x {- a -} \n  -- b\n y";
+        let tokens = tokenize(source);
+        let types = |trivia : &[token::Token<'_>]| trivia.iter()
+            .map(|token| token.token_type).collect::<Vec<_>>();
+        use token::TokenType::*;
+
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].token.token_type, QVarId);
+        assert!(tokens[0].leading.is_empty());
+        assert_eq!(types(&tokens[0].trailing),
+                   vec![Whitespace, MLComment, Whitespace, Newline]);
+        // a comment on a line of its own leads the next token
+        assert_eq!(tokens[1].token.token_type, QVarId);
+        assert_eq!(types(&tokens[1].leading),
+                   vec![Whitespace, SLComment, Newline, Whitespace]);
+        assert_eq!(tokens[1].full_span().start.line, 1);
+        assert_eq!(tokens[2].token.token_type, EndOfFile);
+        assert_eq!(tokens[0].full_text(), "x {- a -} \n");
+    }
+
+    #[test]
+    fn random_sources() {
+        // pieces of Haskell, and of what is not Haskell at all
+        let pieces = ["x", "Foo", "M.f", "where", "import", "::", "->", "+", ":+",
+                      "(", ")", "[", ",", "{-", "-}", "{- c -}", "--", "\"", "'",
+                      " ", "  ", "\t", "\n", "\r\n", "\r", "\x0C", "\u{a0}",
+                      "\u{3bb}", "\u{2192}", "\u{200b}", "0", "_"];
+        let mut random = test_util::Random(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            assert_lossless(&random.pieces(&pieces, 19));
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! The values of literals, decoded from their text, for the tools that
+//! need more than the tokens: integers of any size, floats as exact
+//! rationals, and chars and strings with their escapes and gaps resolved.
+//!
+//! A literal may be well-formed and still have no value, like `'\1114112'`,
+//! which is beyond the last Unicode character; the driver reports such
+//! literals as errors. The surrogates, like `'\xD800'`, are characters in
+//! Haskell, but not in Rust, so the literals with them are fine, they just
+//! have no decoded value.
+
+use crate::reused;
+use crate::string;
+use crate::token;
+
+use num_bigint::{BigInt, BigUint};
+
+use std::fmt;
+
+/// The value of a literal token.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum LiteralValue {
+    Integer(BigUint),
+    /// `mantissa * 10^exponent`, with no trailing zeros in the mantissa,
+    /// so that equal values are equal, `1.50` and `15e-1` alike.
+    Float { mantissa : BigUint, exponent : BigInt },
+    Char(char),
+    String(String),
+}
+
+/// Why a literal has no value. The offsets are the bytes of the escape
+/// in the text of the literal.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Error {
+    /// A numeric escape beyond `\1114111`.
+    OutOfRange { start : usize, end : usize },
+}
+
+impl Error {
+    /// The offsets of the escape, from the beginning of the literal.
+    pub fn escape(&self) -> (usize, usize) {
+        match *self {
+            Error::OutOfRange { start, end } => (start, end),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::OutOfRange { .. } =>
+                write!(f, "numeric escape sequence out of range, the last character is \\1114111"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decodes the text of a token of `token_type`. It is `None` for the
+/// tokens that are not literals, for the literals that are not written the
+/// way the report says, as a lexer specification may lex them, and for the
+/// chars and strings with surrogates in them.
+pub fn decode(token_type : token::TokenType, text : &str)
+              -> Option<Result<LiteralValue, Error>> {
+    match token_type {
+        token::TokenType::IntegerLiteral => integer(text).map(Ok),
+        token::TokenType::FloatLiteral => float(text).map(Ok),
+        token::TokenType::CharLiteral => match unescape(text)? {
+            Ok(decoded) => {
+                let mut chars = decoded.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(character), None) => Some(Ok(LiteralValue::Char(character))),
+                    _ => None,
+                }
+            },
+            Err(error) => Some(Err(error)),
+        },
+        token::TokenType::StringLiteral =>
+            unescape(text).map(|decoded| decoded.map(LiteralValue::String)),
+        _ => None,
+    }
+}
+
+fn integer(text : &str) -> Option<LiteralValue> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0o") | Some("0O") => (&text[2..], 8),
+        Some("0x") | Some("0X") => (&text[2..], 16),
+        _ => (text, 10),
+    };
+    BigUint::parse_bytes(digits.as_bytes(), radix).map(LiteralValue::Integer)
+}
+
+fn float(text : &str) -> Option<LiteralValue> {
+    let (number, exponent) = match text.find(&['e', 'E'][..]) {
+        Some(at) => (&text[..at], &text[at + 1..]),
+        None => (text, "0"),
+    };
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let digits = format!("{}{}", whole, fraction);
+    if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None
+    }
+    let mut exponent : BigInt = exponent.trim_start_matches('+').parse().ok()?;
+    exponent -= fraction.len();
+
+    let significant = digits.trim_end_matches('0');
+    if significant.is_empty() {
+        return Some(LiteralValue::Float { mantissa : BigUint::default(),
+                                          exponent : BigInt::default() })
+    }
+    exponent += digits.len() - significant.len();
+    Some(LiteralValue::Float { mantissa : significant.parse().ok()?, exponent })
+}
+
+/// The text between the quotes of a char or string literal, with the
+/// escapes replaced by what they stand for, and the gaps left out. It has
+/// no value with a surrogate in it, but the escapes after the surrogate may
+/// still be out of range.
+fn unescape(text : &str) -> Option<Result<String, Error>> {
+    let inside = text.get(1..text.len().checked_sub(1)?)?;
+    let mut decoded = String::with_capacity(inside.len());
+    let mut surrogate = false;
+    let mut rest = inside;
+    while let Some(at) = rest.find('\\') {
+        decoded += &rest[..at];
+        let escape = &rest[at + 1..];
+        // from the beginning of the literal, counting the quote
+        let start = 1 + inside.len() - rest.len() + at;
+
+        let (escaped, len) = match escaped(escape, start)? {
+            Ok(escaped) => escaped,
+            Err(error) => return Some(Err(error)),
+        };
+        match escaped {
+            Escaped::Char(character) => decoded.push(character),
+            Escaped::Nothing => {},
+            Escaped::Surrogate => surrogate = true,
+        }
+        rest = &escape[len..];
+    }
+    if surrogate {
+        return None
+    }
+    decoded += rest;
+    Some(Ok(decoded))
+}
+
+/// What an escape stands for.
+enum Escaped {
+    Char(char),
+    /// `\&` and the gaps stand for nothing.
+    Nothing,
+    /// A code point between `\xD800` and `\xDFFF`, which is a Haskell
+    /// `Char`, but not a `char`.
+    Surrogate,
+}
+
+/// What the escape at the beginning of `escape`, after its backslash,
+/// stands for, and how long it is. `start` is where its backslash is.
+fn escaped(escape : &str, start : usize) -> Option<Result<(Escaped, usize), Error>> {
+    let first = escape.chars().next()?;
+    let simple = match first {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0C'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0B'),
+        '\\' | '"' | '\'' => Some(first),
+        _ => None,
+    };
+    if let Some(character) = simple {
+        return Some(Ok((Escaped::Char(character), 1)))
+    }
+
+    match first {
+        '&' => Some(Ok((Escaped::Nothing, 1))),
+        '^' => {
+            let control = escape[1..].chars().next()
+                .filter(|control| ('@'..='_').contains(control))?;
+            Some(Ok((Escaped::Char(char::from_u32(control as u32 - '@' as u32)?), 2)))
+        },
+        'o' | 'x' | '0'..='9' => {
+            let (digits, radix) = match first {
+                'o' => (&escape[1..], 8),
+                'x' => (&escape[1..], 16),
+                _ => (escape, 10),
+            };
+            let len = digits.find(|digit : char| !digit.is_digit(radix))
+                .unwrap_or(digits.len());
+            if len == 0 {
+                return None
+            }
+            let escape_len = escape.len() - digits.len() + len;
+            let end = start + 1 + escape_len;
+            // the digits run on, but the code point stops growing once it
+            // is out of range
+            let code = digits[..len].chars().try_fold(0u32, |code, digit| {
+                code.checked_mul(radix)
+                    .and_then(|code| code.checked_add(digit.to_digit(radix)?))
+                    .filter(|code| *code <= 0x10FFFF)
+            });
+            Some(match code.map(char::from_u32) {
+                Some(Some(character)) => Ok((Escaped::Char(character), escape_len)),
+                Some(None) => Ok((Escaped::Surrogate, escape_len)),
+                None => Err(Error::OutOfRange { start, end }),
+            })
+        },
+        'A'..='Z' => {
+            // the longest name wins, `\SOH` is not `\SO` and `H`
+            let (code, name) = string::ASCII.iter().enumerate()
+                .filter(|(_, name)| escape.starts_with(*name))
+                .max_by_key(|(_, name)| name.len())?;
+            let code = if *name == "DEL" { 127 } else { code as u32 };
+            Some(Ok((Escaped::Char(char::from_u32(code)?), name.len())))
+        },
+        // a gap, up to its closing backslash
+        _ if reused::is_whitechar(first) => Some(Ok((Escaped::Nothing, escape.find('\\')? + 1))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn value(token_type : token::TokenType, text : &str) -> LiteralValue {
+        decode(token_type, text).unwrap().unwrap()
+    }
+
+    fn float(mantissa : u32, exponent : i32) -> LiteralValue {
+        LiteralValue::Float { mantissa : mantissa.into(), exponent : exponent.into() }
+    }
+
+    #[test]
+    fn integers() {
+        use token::TokenType::IntegerLiteral;
+        assert_eq!(value(IntegerLiteral, "42"), LiteralValue::Integer(42u32.into()));
+        assert_eq!(value(IntegerLiteral, "0x1F"), LiteralValue::Integer(31u32.into()));
+        assert_eq!(value(IntegerLiteral, "0O17"), LiteralValue::Integer(15u32.into()));
+        let big = "123456789012345678901234567890";
+        assert_eq!(value(IntegerLiteral, big), LiteralValue::Integer(big.parse().unwrap()));
+        assert_eq!(decode(token::TokenType::QVarId, "x"), None);
+        // lexed by some other specification
+        assert_eq!(decode(IntegerLiteral, "0b101"), None);
+    }
+
+    #[test]
+    fn floats() {
+        use token::TokenType::FloatLiteral;
+        assert_eq!(value(FloatLiteral, "3.14"), float(314, -2));
+        assert_eq!(value(FloatLiteral, "1.50"), float(15, -1));
+        assert_eq!(value(FloatLiteral, "15e-1"), float(15, -1));
+        assert_eq!(value(FloatLiteral, "1e10"), float(1, 10));
+        assert_eq!(value(FloatLiteral, "100.0E+2"), float(1, 4));
+        assert_eq!(value(FloatLiteral, "0.000"), float(0, 0));
+        assert_eq!(value(FloatLiteral, "0e7"), float(0, 0));
+    }
+
+    #[test]
+    fn chars() {
+        use token::TokenType::CharLiteral;
+        for (literal, character) in &[("'a'", 'a'), ("'\\n'", '\n'), ("'\\''", '\''),
+                                      ("'\\^@'", '\0'), ("'\\^['", '\x1B'),
+                                      ("'\\^_'", '\x1F'), ("'\\SO'", '\x0E'),
+                                      ("'\\SOH'", '\x01'), ("'\\DEL'", '\x7F'),
+                                      ("'\\SP'", ' '), ("'\\955'", '\u{3bb}'),
+                                      ("'\\o1'", '\x01'), ("'\\x3BB'", '\u{3bb}'),
+                                      ("'\\1114111'", '\u{10ffff}'),
+                                      ("'\u{2192}'", '\u{2192}')] {
+            assert_eq!(value(CharLiteral, literal), LiteralValue::Char(*character),
+                       "{}", literal);
+        }
+    }
+
+    #[test]
+    fn strings() {
+        use token::TokenType::StringLiteral;
+        let string = |text : &str| LiteralValue::String(text.to_string());
+        assert_eq!(value(StringLiteral, "\"\""), string(""));
+        assert_eq!(value(StringLiteral, "\"a\\tb\""), string("a\tb"));
+        assert_eq!(value(StringLiteral, "\"\\SO\\&H\\SOH\""), string("\x0EH\x01"));
+        assert_eq!(value(StringLiteral, "\"\\1234\\&5\""), string("\u{4d2}5"));
+        assert_eq!(value(StringLiteral, "\"ab\\\n   \\cd\""), string("abcd"));
+        assert_eq!(value(StringLiteral, "\"\\x41\\o101\\65\\^A\""), string("AAA\x01"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(decode(token::TokenType::CharLiteral, "'\\1114112'"),
+                   Some(Err(Error::OutOfRange { start : 1, end : 9 })));
+        assert_eq!(decode(token::TokenType::StringLiteral, "\"ab\\x110000000000000000z\""),
+                   Some(Err(Error::OutOfRange { start : 3, end : 23 })));
+        // surrogates are fine, but have no value
+        assert_eq!(decode(token::TokenType::StringLiteral, "\"\\xD800\""), None);
+        assert_eq!(decode(token::TokenType::CharLiteral, "'\\57343'"), None);
+        assert_eq!(decode(token::TokenType::StringLiteral, "\"\\xD800\\1114112\""),
+                   Some(Err(Error::OutOfRange { start : 7, end : 15 })));
+    }
+}